@@ -4,6 +4,7 @@ fn main() {
     println!("cargo:rustc-link-lib=Xrender");
     println!("cargo:rustc-link-lib=Xfixes");
     println!("cargo:rustc-link-lib=Xext");
+    println!("cargo:rustc-link-lib=Xrandr");
     println!("cargo:rustc-link-lib=GL");
     println!("cargo:rustc-link-lib=GLU");
 }
\ No newline at end of file
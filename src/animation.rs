@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+// how an animation gets from 0.0 to 1.0
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Curve {
+    Linear,
+    EaseInOut,
+    Spring, // overshoots a bit and bounces back, very chaotic
+}
+
+impl Curve {
+    pub fn parse(name: &str) -> Option<Curve> {
+        match name {
+            "linear" => Some(Curve::Linear),
+            "ease-in-out" => Some(Curve::EaseInOut),
+            "spring" => Some(Curve::Spring),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Curve::Spring => {
+                if t >= 1.0 {
+                    return 1.0;
+                }
+                1.0 - (-6.0 * t).exp() * (t * std::f32::consts::PI * 3.0).cos()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AnimationKind {
+    Map,
+    Unmap,
+    Destroy,
+    Minimize,
+    Restore,
+}
+
+impl AnimationKind {
+    // does the window disappear once this animation is over?
+    pub fn is_closing(&self) -> bool {
+        matches!(self, AnimationKind::Unmap | AnimationKind::Destroy | AnimationKind::Minimize)
+    }
+}
+
+// curve and duration for one kind of animation, set in the config
+#[derive(Clone, Copy)]
+pub struct AnimationSettings {
+    pub curve: Curve,
+    pub duration_ms: u64,
+}
+
+// what an animation does to a window while it's being drawn
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub opacity: f32, // multiplied with the window's own opacity
+    pub scale: f32, // around the centre of the frame
+    pub offset_x: f32, // moves the centre of the frame
+    pub offset_y: f32, // moves the centre of the frame
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { opacity: 1.0, scale: 1.0, offset_x: 0.0, offset_y: 0.0 };
+    // what a window looks like just before it opens or just after it closes
+    pub const HIDDEN: Transform = Transform { opacity: 0.0, scale: 0.85, offset_x: 0.0, offset_y: 0.0 };
+
+    fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            opacity: self.opacity + (other.opacity - self.opacity) * t,
+            scale: self.scale + (other.scale - self.scale) * t,
+            offset_x: self.offset_x + (other.offset_x - self.offset_x) * t,
+            offset_y: self.offset_y + (other.offset_y - self.offset_y) * t,
+        }
+    }
+
+    // shrinks a frame at (x, y, width, height) into the target rectangle, e.g. a taskbar button
+    pub fn towards_rect(frame: (f32, f32, f32, f32), target: (f32, f32, f32, f32)) -> Transform {
+        let scale = if frame.2 > 0.0 { (target.2 / frame.2).clamp(0.05, 1.0) } else { 0.05 };
+        Transform {
+            opacity: 0.0,
+            scale,
+            offset_x: (target.0 + target.2 / 2.0) - (frame.0 + frame.2 / 2.0),
+            offset_y: (target.1 + target.3 / 2.0) - (frame.1 + frame.3 / 2.0),
+        }
+    }
+}
+
+pub struct Animation {
+    pub kind: AnimationKind,
+    started: Instant,
+    duration: Duration,
+    curve: Curve,
+    from: Transform,
+    to: Transform,
+}
+
+impl Animation {
+    pub fn new(kind: AnimationKind, from: Transform, to: Transform, settings: AnimationSettings) -> Self {
+        Animation {
+            kind,
+            started: Instant::now(),
+            duration: Duration::from_millis(settings.duration_ms),
+            curve: settings.curve,
+            from,
+            to,
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        self.started.elapsed().as_secs_f32() / self.duration.as_secs_f32()
+    }
+
+    pub fn current(&self) -> Transform {
+        let progress = self.progress();
+        if progress >= 1.0 {
+            return self.to;
+        }
+        self.from.lerp(&self.to, self.curve.apply(progress))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress() >= 1.0
+    }
+}
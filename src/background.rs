@@ -0,0 +1,334 @@
+// the wallpaper behind every window: one per workspace, optionally a slideshow, crossfading whenever it changes
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use libsex::bindings::*;
+use crate::config::Config;
+use crate::layout::Rect;
+use crate::renderer::{DrawParams, Fill, Renderer};
+use crate::theme::dominant_color;
+use crate::wallpaper::{self, RgbaImage, WallpaperMode};
+
+// what a slideshow directory can have in it, anything else is skipped
+const IMAGE_EXTENSIONS: [&str; 8] = ["png", "jpg", "jpeg", "ppm", "pgm", "pnm", "pbm", "hdr"];
+
+// a wallpaper scaled to the screen and uploaded
+struct WallpaperTexture {
+    texture: GLuint,
+    color: [f32; 3], // dominant colour, for themes
+    image: RgbaImage, // what got uploaded, for giving the desktop window again without decoding it again
+}
+
+// what the loading thread gets asked for
+struct LoadRequest {
+    path: PathBuf,
+    generation: u64,
+    width: u32,
+    height: u32,
+    monitors: Vec<Rect>,
+}
+
+// what the loading thread sends back
+struct LoadedWallpaper {
+    path: PathBuf,
+    generation: u64, // which screen size it was scaled for
+    image: RgbaImage,
+    color: [f32; 3],
+}
+
+pub struct Background {
+    width: u32,
+    height: u32,
+    monitors: Vec<Rect>, // every monitor gets its own copy of the wallpaper
+    letterbox_color: [f32; 3],
+    wallpaper: PathBuf, // used when nothing more specific is set
+    workspace_wallpapers: HashMap<usize, PathBuf>,
+    slideshow: Vec<PathBuf>, // empty if there's no slideshow
+    slideshow_index: usize,
+    slideshow_interval: Duration,
+    last_slide: Instant,
+    fade_duration: Duration,
+    workspace: usize,
+    textures: HashMap<PathBuf, WallpaperTexture>,
+    current: PathBuf,
+    previous: Option<PathBuf>, // fading out, until fade_start + fade_duration
+    fade_start: Instant,
+    changed: Option<RgbaImage>, // a new wallpaper that the desktop window and root pixmap haven't been given yet
+    // decoding and scaling takes far longer than a frame, so it happens on another thread while the old wallpaper stays up
+    loading: Option<(PathBuf, bool)>, // the wallpaper being loaded, and whether to fade to it once it's ready
+    generation: u64, // goes up whenever the screen changes, so pictures scaled for the old one get thrown away
+    stale: Option<WallpaperTexture>, // the wallpaper from before the screen changed, drawn until the new one's ready
+    requests: Sender<LoadRequest>,
+    receiver: Receiver<LoadedWallpaper>,
+}
+
+// every image in a directory, in name order
+fn read_slideshow(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("could not read slideshow directory {}: {}", dir.display(), e);
+            return Vec::new();
+        }
+    };
+    let mut images: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str())))
+        .collect();
+    images.sort();
+    images
+}
+
+impl Background {
+    pub fn new(config: &Config, width: u32, height: u32, monitors: &[Rect]) -> Self {
+        let slideshow = config.wallpaper_slideshow.as_ref().map(|dir| read_slideshow(dir)).unwrap_or_default();
+        let (requests, receiver) = spawn_loader(config.wallpaper_mode, config.wallpaper_letterbox_color);
+        let mut background = Background {
+            width,
+            height,
+            monitors: monitors.to_vec(),
+            letterbox_color: config.wallpaper_letterbox_color,
+            wallpaper: config.wallpaper.clone(),
+            workspace_wallpapers: config.workspace_wallpapers.clone(),
+            slideshow,
+            slideshow_index: 0,
+            slideshow_interval: Duration::from_secs(config.wallpaper_interval),
+            last_slide: Instant::now(),
+            fade_duration: Duration::from_millis(config.wallpaper_fade_ms),
+            workspace: 0,
+            textures: HashMap::new(),
+            current: PathBuf::new(),
+            previous: None,
+            fade_start: Instant::now(),
+            changed: None,
+            loading: None,
+            generation: 0,
+            stale: None,
+            requests,
+            receiver,
+        };
+        // nothing to fade from at startup
+        let path = background.wanted_path();
+        background.show(path, false);
+        background
+    }
+
+    // the wallpaper the current workspace should have right now
+    fn wanted_path(&self) -> PathBuf {
+        if let Some(path) = self.workspace_wallpapers.get(&self.workspace) {
+            return path.clone();
+        }
+        match self.slideshow.get(self.slideshow_index) {
+            Some(path) => path.clone(),
+            None => self.wallpaper.clone(),
+        }
+    }
+
+    // starts fading to the wallpaper at path, once it's loaded if it isn't already
+    fn show(&mut self, path: PathBuf, fade: bool) {
+        if path == self.current {
+            // whatever was being loaded isn't wanted any more
+            self.loading = None;
+            return;
+        }
+        if let Some(wallpaper) = self.textures.get(&path) {
+            // still have it, but the desktop window needs the picture again
+            self.changed = Some(wallpaper.image.clone());
+            self.loading = None;
+            self.switch_to(path, fade);
+            return;
+        }
+        if self.loading.as_ref().map_or(false, |(loading, _)| *loading == path) {
+            return;
+        }
+        self.loading = Some((path.clone(), fade));
+        let request = LoadRequest {
+            path,
+            generation: self.generation,
+            width: self.width,
+            height: self.height,
+            monitors: self.monitors.clone(),
+        };
+        if self.requests.send(request).is_err() {
+            println!("warning: the wallpaper loading thread is gone, keeping the current wallpaper");
+            self.loading = None;
+        }
+    }
+
+    // makes an uploaded wallpaper the current one; there's nothing to fade from if nothing was up yet
+    fn switch_to(&mut self, path: PathBuf, fade: bool) {
+        let previous = std::mem::replace(&mut self.current, path);
+        self.previous = if fade && !previous.as_os_str().is_empty() { Some(previous) } else { None };
+        self.fade_start = Instant::now();
+        self.forget_unused();
+    }
+
+    // picks up a wallpaper the loading thread finished, if it's still the one we want; returns true if it went up
+    fn receive_loaded(&mut self) -> bool {
+        let mut shown = false;
+        while let Ok(loaded) = self.receiver.try_recv() {
+            let fade = match &self.loading {
+                Some((path, fade)) if *path == loaded.path && loaded.generation == self.generation => *fade,
+                _ => continue,
+            };
+            self.loading = None;
+            let texture = upload(&loaded.image);
+            self.changed = Some(loaded.image.clone());
+            self.textures.insert(loaded.path.clone(), WallpaperTexture { texture, color: loaded.color, image: loaded.image });
+            if let Some(stale) = self.stale.take() {
+                unsafe {
+                    glDeleteTextures(1, &stale.texture);
+                }
+            }
+            self.switch_to(loaded.path, fade);
+            shown = true;
+        }
+        shown
+    }
+
+    // monitors got plugged in, unplugged or moved around; everything gets scaled again, without a fade
+    pub fn resize(&mut self, width: u32, height: u32, monitors: &[Rect]) {
+        self.width = width;
+        self.height = height;
+        self.monitors = monitors.to_vec();
+        self.generation += 1;
+        // keep the old wallpaper up, stretched, until the new one's ready
+        if let Some(current) = self.textures.remove(&self.current) {
+            if let Some(stale) = self.stale.replace(current) {
+                unsafe {
+                    glDeleteTextures(1, &stale.texture);
+                }
+            }
+        }
+        self.current = PathBuf::new();
+        self.previous = None;
+        self.loading = None;
+        self.forget_unused();
+        let path = self.wanted_path();
+        self.show(path, false);
+    }
+
+    // screen sized textures add up quickly, so only keep the ones on screen
+    fn forget_unused(&mut self) {
+        let current = &self.current;
+        let previous = &self.previous;
+        self.textures.retain(|path, wallpaper| {
+            let keep = path == current || previous.as_ref() == Some(path);
+            if !keep {
+                unsafe {
+                    glDeleteTextures(1, &wallpaper.texture);
+                }
+            }
+            keep
+        });
+    }
+
+    pub fn set_workspace(&mut self, workspace: usize) {
+        self.workspace = workspace;
+        let path = self.wanted_path();
+        self.show(path, true);
+    }
+
+    // moves the slideshow along and finishes fades; returns true while something is changing on screen
+    pub fn update(&mut self) -> bool {
+        if !self.slideshow.is_empty() && self.last_slide.elapsed() >= self.slideshow_interval {
+            self.last_slide = Instant::now();
+            self.slideshow_index = (self.slideshow_index + 1) % self.slideshow.len();
+            let path = self.wanted_path();
+            self.show(path, true);
+        }
+        let shown = self.receive_loaded();
+        if self.previous.is_some() && self.fade_start.elapsed() >= self.fade_duration {
+            self.previous = None;
+            self.forget_unused();
+        }
+        shown || self.previous.is_some()
+    }
+
+    // the picture of a wallpaper that just became current, for the desktop window and root pixmap
+    pub fn take_changed(&mut self) -> Option<RgbaImage> {
+        self.changed.take()
+    }
+
+    // the dominant colour of the wallpaper on screen, for themes
+    pub fn color(&self) -> [f32; 3] {
+        self.textures.get(&self.current).or(self.stale.as_ref()).map_or(self.letterbox_color, |wallpaper| wallpaper.color)
+    }
+
+    pub fn draw(&self, renderer: &mut Renderer) {
+        let size = (self.width as f32, self.height as f32);
+        let vertices = [
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            size.0, 0.0, 1.0, 0.0, 1.0, 0.0,
+            0.0, size.1, 0.0, 1.0, 0.0, 1.0,
+            size.0, size.1, 1.0, 1.0, 1.0, 1.0,
+        ];
+        let indices = [0, 1, 2, 1, 3, 2];
+        let mut opacity = 1.0;
+        if let Some(previous) = self.previous.as_ref().and_then(|path| self.textures.get(path)) {
+            renderer.queue(DrawParams::new(Fill::Texture(previous.texture), 1.0, size, 0.0), GL_TRIANGLES, &vertices, &indices);
+            opacity = (self.fade_start.elapsed().as_secs_f32() / self.fade_duration.as_secs_f32().max(0.001)).min(1.0);
+        }
+        if let Some(current) = self.textures.get(&self.current).or(self.stale.as_ref()) {
+            renderer.queue(DrawParams::new(Fill::Texture(current.texture), opacity, size, 0.0), GL_TRIANGLES, &vertices, &indices);
+        }
+    }
+}
+
+// starts the one thread wallpapers get loaded on. it only ever loads the newest request, anything asked for while it
+// was busy has already been replaced in Background::loading anyway; it stops once the background's gone
+fn spawn_loader(mode: WallpaperMode, letterbox_color: [f32; 3]) -> (Sender<LoadRequest>, Receiver<LoadedWallpaper>) {
+    let (requests, request_receiver) = mpsc::channel::<LoadRequest>();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        while let Ok(mut request) = request_receiver.recv() {
+            while let Ok(newer) = request_receiver.try_recv() {
+                request = newer;
+            }
+            let image = load(&request.path, request.width, request.height, &request.monitors, mode, letterbox_color);
+            let color = dominant_color(&image.data);
+            let loaded = LoadedWallpaper { path: request.path, generation: request.generation, image, color };
+            if sender.send(loaded).is_err() {
+                break;
+            }
+        }
+    });
+    (requests, receiver)
+}
+
+// the wallpaper scaled to each monitor, or a solid colour if it can't be loaded
+fn load(path: &Path, width: u32, height: u32, monitors: &[Rect], mode: WallpaperMode, letterbox_color: [f32; 3]) -> RgbaImage {
+    match wallpaper::load(path) {
+        Ok(image) if image.width > 0 && image.height > 0 => wallpaper::render_monitors(&image, path, width, height, monitors, mode, letterbox_color),
+        Ok(_) => {
+            println!("warning: wallpaper {} is empty, using a solid colour", path.display());
+            wallpaper::solid(width, height, letterbox_color)
+        }
+        Err(e) => {
+            println!("warning: could not load wallpaper {}: {}, using a solid colour", path.display(), e);
+            wallpaper::solid(width, height, letterbox_color)
+        }
+    }
+}
+
+fn upload(image: &RgbaImage) -> GLuint {
+    let mut texture: GLuint = 0;
+    unsafe {
+        glGenTextures(1, &mut texture);
+        glBindTexture(GL_TEXTURE_2D, texture);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+        // the wallpaper is always drawn opaque
+        glTexImage2D(GL_TEXTURE_2D, 0, GL_RGB8 as GLint, image.width as i32, image.height as i32, 0,
+                     GL_RGBA, GL_UNSIGNED_BYTE, image.data.as_ptr() as *const c_void);
+    }
+    texture
+}
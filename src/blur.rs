@@ -0,0 +1,152 @@
+use std::ffi::c_void;
+use std::ptr::null;
+use libsex::bindings::*;
+use crate::renderer::{compile_shader_program, ortho, uniform_location, Renderer, VERTEX_SHADER};
+
+// kawase blur: average four diagonal samples that get further apart every pass
+const BLUR_FRAGMENT_SHADER: &str = "
+#version 120
+varying vec2 Texcoord;
+
+uniform sampler2D tex;
+uniform vec2 pixel; // size of one texel
+uniform vec2 limit; // the region we copied ends here, don't sample past it
+uniform float offset;
+
+vec4 tap(vec2 position)
+{
+return texture2D(tex, clamp(position, pixel * 0.5, limit - pixel * 0.5));
+}
+
+void main()
+{
+vec2 uv = Texcoord;
+vec2 o = pixel * (offset + 0.5);
+gl_FragColor = (tap(uv + vec2(-o.x, -o.y)) + tap(uv + vec2(o.x, -o.y))
+              + tap(uv + vec2(-o.x, o.y)) + tap(uv + vec2(o.x, o.y))) * 0.25;
+}
+";
+
+// blurs whatever has already been drawn behind a window, using two screen sized offscreen framebuffers
+pub struct Blur {
+    program: GLuint,
+    framebuffers: [GLuint; 2],
+    textures: [GLuint; 2],
+    width: i32, // size of the textures, same as the screen
+    height: i32,
+    passes: u32,
+}
+
+impl Blur {
+    pub fn new(width: i32, height: i32, passes: u32) -> Result<Blur, String> {
+        let program = compile_shader_program(VERTEX_SHADER, BLUR_FRAGMENT_SHADER)?;
+        let mut blur = Blur {
+            program,
+            framebuffers: [0; 2],
+            textures: [0; 2],
+            width,
+            height,
+            passes: passes.max(1),
+        };
+        unsafe {
+            glGenFramebuffers(2, blur.framebuffers.as_mut_ptr());
+            glGenTextures(2, blur.textures.as_mut_ptr());
+            for i in 0..2 {
+                glBindTexture(GL_TEXTURE_2D, blur.textures[i]);
+                glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+                glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+                glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+                glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+                // no alpha, the blurred background is always drawn fully opaque
+                glTexImage2D(GL_TEXTURE_2D, 0, GL_RGB8 as GLint, width, height, 0, GL_RGB, GL_UNSIGNED_BYTE, null::<c_void>());
+
+                glBindFramebuffer(GL_FRAMEBUFFER, blur.framebuffers[i]);
+                glFramebufferTexture2D(GL_FRAMEBUFFER, GL_COLOR_ATTACHMENT0, GL_TEXTURE_2D, blur.textures[i], 0);
+                if glCheckFramebufferStatus(GL_FRAMEBUFFER) != GL_FRAMEBUFFER_COMPLETE {
+                    glBindFramebuffer(GL_FRAMEBUFFER, 0);
+                    return Err("blur framebuffer is incomplete".to_string());
+                }
+            }
+            glBindFramebuffer(GL_FRAMEBUFFER, 0);
+        }
+        Ok(blur)
+    }
+
+    // the textures have to stay the size of the screen; the framebuffers keep pointing at them
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        unsafe {
+            for texture in self.textures {
+                glBindTexture(GL_TEXTURE_2D, texture);
+                glTexImage2D(GL_TEXTURE_2D, 0, GL_RGB8 as GLint, width, height, 0, GL_RGB, GL_UNSIGNED_BYTE, null::<c_void>());
+            }
+        }
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    // blurs the (x, y, width, height) part of what has been drawn so far (top left origin, like x) and returns the texture
+    // holding the result, plus the screen position of the texture's origin (the bottom left of the part that was on screen)
+    pub fn blur_region(&self, renderer: &mut Renderer, x: i32, y: i32, width: i32, height: i32) -> Option<(GLuint, i32, i32)> {
+        // only the part that's actually on screen can be read back
+        let left = x.max(0);
+        let top = y.max(0);
+        let right = (x + width).min(self.width);
+        let bottom = (y + height).min(self.height);
+        if right <= left || bottom <= top {
+            return None;
+        }
+        let width = right - left;
+        let height = bottom - top;
+
+        // whatever is behind the window has to actually be in the back buffer before we can copy it
+        renderer.flush();
+
+        unsafe {
+            // that might be the post-process framebuffer rather than the screen
+            let mut target_framebuffer: GLint = 0;
+            glGetIntegerv(GL_FRAMEBUFFER_BINDING, &mut target_framebuffer);
+
+            glBindTexture(GL_TEXTURE_2D, self.textures[0]);
+            glCopyTexSubImage2D(GL_TEXTURE_2D, 0, 0, 0, left, self.height - bottom, width, height);
+
+            glViewport(0, 0, width, height);
+            glDisable(GL_BLEND);
+
+            glUseProgram(self.program);
+            let s = width as f32 / self.width as f32;
+            let t = height as f32 / self.height as f32;
+            glUniform1i(uniform_location(self.program, b"tex\0"), 0);
+            glUniform2f(uniform_location(self.program, b"pixel\0"), 1.0 / self.width as f32, 1.0 / self.height as f32);
+            glUniform2f(uniform_location(self.program, b"limit\0"), s, t);
+            let offset_loc = uniform_location(self.program, b"offset\0");
+
+            // the framebuffers have their origin at the bottom left, same as the textures
+            let projection = ortho(0.0, width as f32, 0.0, height as f32);
+            let vertices = [
+                0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+                width as f32, 0.0, s, 0.0, 1.0, 0.0,
+                0.0, height as f32, 0.0, t, 0.0, 1.0,
+                width as f32, height as f32, s, t, 1.0, 1.0,
+            ];
+            let indices = [0u32, 1, 2, 1, 3, 2];
+            for pass in 0..self.passes {
+                let source = (pass % 2) as usize;
+                glBindFramebuffer(GL_FRAMEBUFFER, self.framebuffers[1 - source]);
+                glBindTexture(GL_TEXTURE_2D, self.textures[source]);
+                glUniform1f(offset_loc, pass as f32);
+                renderer.draw_with_program(self.program, &projection, &vertices, &indices);
+            }
+            glBindFramebuffer(GL_FRAMEBUFFER, target_framebuffer as GLuint);
+
+            let (screen_width, screen_height) = renderer.size();
+            glViewport(0, 0, screen_width, screen_height);
+            glEnable(GL_BLEND);
+
+            Some((self.textures[(self.passes % 2) as usize], left, bottom))
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::animation::{AnimationKind, AnimationSettings, Curve};
+use crate::layout::{self, LayoutSettings};
+use crate::theme::{Theme, ThemeMode};
+use crate::types::WindowType;
+use crate::transition::TransitionStyle;
+use crate::wallpaper::WallpaperMode;
+
+// per-window overrides, set with "rule.<class>.<setting> = value" where <class> is either part of WM_CLASS
+#[derive(Clone, Copy, Default)]
+pub struct RuleSettings {
+    pub opacity: Option<f32>, // replaces _NET_WM_WINDOW_OPACITY
+    pub inactive_opacity: Option<f32>, // replaces the global inactive_opacity
+    pub shadow: Option<bool>, // force the shadow on or off
+    pub blur: Option<bool>, // force blurring what's behind the window on or off
+    pub rounded_corners: Option<bool>, // false for square corners
+    pub shader: Option<usize>, // index into Config.shaders
+    pub inactive_shader: Option<usize>, // used instead of shader while the window doesn't have focus
+    pub floating: Option<bool>, // true to keep the window out of tiling layouts
+}
+
+impl RuleSettings {
+    // anything set in other wins
+    fn merge(&mut self, other: &RuleSettings) {
+        self.opacity = other.opacity.or(self.opacity);
+        self.inactive_opacity = other.inactive_opacity.or(self.inactive_opacity);
+        self.shadow = other.shadow.or(self.shadow);
+        self.blur = other.blur.or(self.blur);
+        self.rounded_corners = other.rounded_corners.or(self.rounded_corners);
+        self.shader = other.shader.or(self.shader);
+        self.inactive_shader = other.inactive_shader.or(self.inactive_shader);
+        self.floating = other.floating.or(self.floating);
+    }
+}
+
+pub struct Rule {
+    pub class: String, // matched against both the instance and class names from WM_CLASS
+    pub settings: RuleSettings,
+}
+
+// settings read from cwm.conf, one "key = value" per line; lines starting with # are comments
+pub struct Config {
+    pub wobbly_grid_width: usize, // amount of control points across each window
+    pub wobbly_grid_height: usize, // amount of control points down each window
+    pub wobbly_spring_k: f32, // how stiff the springs between control points are
+    pub wobbly_friction: f32, // how much speed each control point loses per frame (0.0 - 1.0)
+    pub map_animation: AnimationSettings,
+    pub unmap_animation: AnimationSettings,
+    pub destroy_animation: AnimationSettings,
+    pub minimize_animation: AnimationSettings,
+    pub restore_animation: AnimationSettings,
+    pub workspace_animation: AnimationSettings,
+    pub workspace_animation_style: TransitionStyle, // how switching workspaces looks
+    pub inactive_opacity: f32, // opacity of windows that don't have focus
+    pub fade_step: f32, // how much opacity can change per 60hz frame when fading (1.0 = instantly)
+    pub shadows: bool, // draw drop shadows at all
+    pub shadow_radius: u16, // how far the shadow blurs out past the frame
+    pub shadow_offset_x: f32,
+    pub shadow_offset_y: f32,
+    pub shadow_color: [f32; 3],
+    pub shadow_opacity: f32,
+    pub shadow_exclude: Vec<WindowType>, // window types that never get a shadow
+    pub blur: bool, // blur behind translucent windows even if they didn't ask for it
+    pub blur_passes: u32, // more passes = blurrier and slower
+    pub corner_radius: f32, // in pixels, 0 for square corners
+    pub shaders: Vec<PathBuf>, // every custom fragment shader mentioned anywhere, the other settings point into this
+    pub post_process_shader: Option<usize>, // run over the whole screen after everything else is drawn
+    pub window_shader: Option<usize>, // for every window that doesn't have a rule saying otherwise
+    pub inactive_shader: Option<usize>, // same, but for windows that don't have focus
+    pub border_theme: Theme, // borders of the focused window
+    pub inactive_border_theme: Option<Theme>, // borders of every other window, same as border_theme if not set
+    pub background_theme: Theme, // the clear colour, seen wherever nothing else gets drawn
+    pub workspaces: usize, // how many virtual desktops there are
+    pub workspace_names: Vec<String>, // for pagers; workspaces without a name are called by their number
+    pub layout: usize, // index into layout::builtin(), for workspaces that don't have their own
+    pub workspace_layouts: HashMap<usize, usize>, // by workspace index, from 0
+    pub tiling: LayoutSettings,
+    pub snap_threshold: i32, // how close (in pixels) a dragged window has to get to an edge to snap to it, 0 turns snapping off
+    pub edge_tiling: bool, // dragging to the top of the screen maximizes, dragging to the sides tiles into that half
+    pub unredirect_fullscreen: bool, // stop compositing while an opaque fullscreen window covers everything
+    pub wallpaper: PathBuf, // png, jpeg, ppm/pnm or hdr; --wallpaper on the command line wins over this
+    pub workspace_wallpapers: HashMap<usize, PathBuf>, // by workspace index, from 0
+    pub wallpaper_slideshow: Option<PathBuf>, // a directory of images to rotate through
+    pub wallpaper_interval: u64, // seconds between slideshow images
+    pub wallpaper_fade_ms: u64, // how long the crossfade between wallpapers takes
+    pub wallpaper_mode: WallpaperMode,
+    pub wallpaper_letterbox_color: [f32; 3], // around the wallpaper when it doesn't cover the screen
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            wobbly_grid_width: 4,
+            wobbly_grid_height: 4,
+            wobbly_spring_k: 0.3,
+            wobbly_friction: 0.25,
+            map_animation: AnimationSettings { curve: Curve::Spring, duration_ms: 250 },
+            unmap_animation: AnimationSettings { curve: Curve::EaseInOut, duration_ms: 150 },
+            destroy_animation: AnimationSettings { curve: Curve::EaseInOut, duration_ms: 150 },
+            minimize_animation: AnimationSettings { curve: Curve::EaseInOut, duration_ms: 300 },
+            restore_animation: AnimationSettings { curve: Curve::Spring, duration_ms: 300 },
+            workspace_animation: AnimationSettings { curve: Curve::EaseInOut, duration_ms: 350 },
+            workspace_animation_style: TransitionStyle::Slide,
+            inactive_opacity: 1.0,
+            fade_step: 0.05,
+            shadows: true,
+            shadow_radius: 12,
+            shadow_offset_x: 0.0,
+            shadow_offset_y: 6.0,
+            shadow_color: [0.0, 0.0, 0.0],
+            shadow_opacity: 0.5,
+            shadow_exclude: vec![WindowType::Dock, WindowType::Desktop],
+            blur: true,
+            blur_passes: 4,
+            corner_radius: 8.0,
+            shaders: Vec::new(),
+            post_process_shader: None,
+            window_shader: None,
+            inactive_shader: None,
+            border_theme: Theme::default(),
+            inactive_border_theme: None,
+            background_theme: Theme::default(),
+            workspaces: 4,
+            workspace_names: Vec::new(),
+            layout: 0,
+            workspace_layouts: HashMap::new(),
+            tiling: LayoutSettings::default(),
+            snap_threshold: 15,
+            edge_tiling: true,
+            unredirect_fullscreen: true,
+            wallpaper: PathBuf::from("bg.png"),
+            workspace_wallpapers: HashMap::new(),
+            wallpaper_slideshow: None,
+            wallpaper_interval: 300,
+            wallpaper_fade_ms: 1000,
+            wallpaper_mode: WallpaperMode::Fill,
+            wallpaper_letterbox_color: [0.0, 0.0, 0.0],
+            rules: Vec::new(),
+        }
+    }
+}
+
+fn parse_curve(key: &str, value: &str) -> Result<Curve, String> {
+    Curve::parse(value).ok_or_else(|| format!("invalid value for {}: {} (expected linear, ease-in-out or spring)", key, value))
+}
+
+// "#rrggbb" (the # is optional) into 0.0 - 1.0 components
+pub fn parse_color(key: &str, value: &str) -> Result<[f32; 3], String> {
+    let hex = value.trim_start_matches('#');
+    let rgb = match u32::from_str_radix(hex, 16) {
+        Ok(rgb) if hex.len() == 6 => rgb,
+        _ => return Err(format!("invalid colour for {}: {} (expected #rrggbb)", key, value)),
+    };
+    Ok([((rgb >> 16) & 0xff) as f32 / 255.0, ((rgb >> 8) & 0xff) as f32 / 255.0, (rgb & 0xff) as f32 / 255.0])
+}
+
+// "dock, desktop" into a list of window types
+fn parse_window_types(key: &str, value: &str) -> Result<Vec<WindowType>, String> {
+    value.split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| WindowType::parse(name).ok_or_else(|| format!("invalid window type for {}: {}", key, name)))
+        .collect()
+}
+
+fn parse_layout(key: &str, value: &str) -> Result<usize, String> {
+    layout::index_of(value)
+        .ok_or_else(|| format!("invalid value for {}: {} (expected floating, master-stack, columns, monocle or bsp)", key, value))
+}
+
+// the part of a theme key after the border_/inactive_border_/background_ prefix
+fn set_theme(theme: &mut Theme, key: &str, setting: &str, value: &str) -> Result<(), String> {
+    match setting {
+        "theme" => theme.mode = ThemeMode::parse(value)
+            .ok_or_else(|| format!("invalid value for {}: {} (expected static, rainbow, gradient, pulse or wallpaper)", key, value))?,
+        "color" => theme.color = parse_color(key, value)?,
+        "gradient_end" => theme.gradient_end = parse_color(key, value)?,
+        "frequency" => theme.frequency = parse_value(key, value)?,
+        "phase" => theme.phase = parse_value(key, value)?,
+        _ => return Err(format!("unknown key {}", key)),
+    }
+    Ok(())
+}
+
+// ~/ means the home directory, and relative paths are relative to the config directory
+fn expand_path(value: &str) -> PathBuf {
+    let path = match value.strip_prefix("~/") {
+        Some(rest) => env::var("HOME").map(|home| PathBuf::from(home).join(rest)).unwrap_or_else(|_| PathBuf::from(value)),
+        None => PathBuf::from(value),
+    };
+    match Config::default_path().as_ref().and_then(|config| config.parent()) {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+impl Config {
+    // where to look for the config file: $XDG_CONFIG_HOME/cwm/cwm.conf, then ~/.config/cwm/cwm.conf
+    pub fn default_path() -> Option<PathBuf> {
+        if let Ok(dir) = env::var("XDG_CONFIG_HOME") {
+            return Some(PathBuf::from(dir).join("cwm").join("cwm.conf"));
+        }
+        env::var("HOME").ok().map(|home| PathBuf::from(home).join(".config").join("cwm").join("cwm.conf"))
+    }
+
+    pub fn load() -> Self {
+        let mut config = Config::default();
+        let path = match Config::default_path() {
+            Some(path) => path,
+            None => return config,
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                println!("no config file at {}, using defaults", path.display());
+                return config;
+            }
+        };
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => {
+                    println!("{}:{}: expected key = value", path.display(), number + 1);
+                    continue;
+                }
+            };
+            if let Err(e) = config.set(key, value) {
+                println!("{}:{}: {}", path.display(), number + 1, e);
+            }
+        }
+        config
+    }
+
+    // command line arguments win over the config file
+    pub fn apply_args(&mut self, mut args: impl Iterator<Item = String>) {
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--wallpaper" => match args.next() {
+                    Some(path) => self.wallpaper = PathBuf::from(path),
+                    None => println!("--wallpaper needs a path"),
+                },
+                _ => println!("unknown argument {}", arg),
+            }
+        }
+    }
+
+    pub fn animation_settings(&self, kind: AnimationKind) -> AnimationSettings {
+        match kind {
+            AnimationKind::Map => self.map_animation,
+            AnimationKind::Unmap => self.unmap_animation,
+            AnimationKind::Destroy => self.destroy_animation,
+            AnimationKind::Minimize => self.minimize_animation,
+            AnimationKind::Restore => self.restore_animation,
+        }
+    }
+
+    // every rule matching the window, later rules in the file win
+    pub fn rule_for(&self, instance: &str, class: &str) -> RuleSettings {
+        let mut settings = RuleSettings::default();
+        for rule in self.rules.iter().filter(|rule| rule.class == instance || rule.class == class) {
+            settings.merge(&rule.settings);
+        }
+        settings
+    }
+
+    fn set_rule(&mut self, class: &str, setting: &str, value: &str) -> Result<(), String> {
+        let index = match self.rules.iter().position(|rule| rule.class == class) {
+            Some(index) => index,
+            None => {
+                self.rules.push(Rule { class: class.to_string(), settings: RuleSettings::default() });
+                self.rules.len() - 1
+            }
+        };
+        // shaders go in a list so the rule settings can stay Copy
+        if setting == "shader" || setting == "inactive_shader" {
+            let shader = Some(self.add_shader(value));
+            let settings = &mut self.rules[index].settings;
+            if setting == "shader" {
+                settings.shader = shader;
+            } else {
+                settings.inactive_shader = shader;
+            }
+            return Ok(());
+        }
+        let settings = &mut self.rules[index].settings;
+        match setting {
+            "opacity" => settings.opacity = Some(parse_value::<f32>(setting, value)?.clamp(0.0, 1.0)),
+            "inactive_opacity" => settings.inactive_opacity = Some(parse_value::<f32>(setting, value)?.clamp(0.0, 1.0)),
+            "shadow" => settings.shadow = Some(parse_value(setting, value)?),
+            "blur" => settings.blur = Some(parse_value(setting, value)?),
+            "rounded_corners" => settings.rounded_corners = Some(parse_value(setting, value)?),
+            "floating" => settings.floating = Some(parse_value(setting, value)?),
+            _ => return Err(format!("unknown rule setting {}", setting)),
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        if let Some(rule) = key.strip_prefix("rule.") {
+            // class names can have dots in them, the setting can't
+            return match rule.rsplit_once('.') {
+                Some((class, setting)) => self.set_rule(class, setting, value),
+                None => Err(format!("expected rule.<class>.<setting>, got {}", key)),
+            };
+        }
+        // wallpaper.<n>, where workspaces are counted from 1 like everyone else does
+        if let Some(workspace) = key.strip_prefix("wallpaper.") {
+            let workspace = parse_value::<usize>(key, workspace)?;
+            if workspace == 0 {
+                return Err(format!("workspaces start at 1, got {}", key));
+            }
+            self.workspace_wallpapers.insert(workspace - 1, expand_path(value));
+            return Ok(());
+        }
+        // layout.<n>, counted from 1 like wallpaper.<n>
+        if let Some(workspace) = key.strip_prefix("layout.") {
+            let workspace = parse_value::<usize>(key, workspace)?;
+            if workspace == 0 {
+                return Err(format!("workspaces start at 1, got {}", key));
+            }
+            self.workspace_layouts.insert(workspace - 1, parse_layout(key, value)?);
+            return Ok(());
+        }
+        // themes are <border|inactive_border|background>_<setting>
+        if let Some(setting) = key.strip_prefix("inactive_border_") {
+            // starts out as a copy of the active theme, so only the differences need setting
+            let mut theme = self.inactive_border_theme.unwrap_or(self.border_theme);
+            set_theme(&mut theme, key, setting, value)?;
+            self.inactive_border_theme = Some(theme);
+            return Ok(());
+        }
+        if let Some(setting) = key.strip_prefix("border_") {
+            return set_theme(&mut self.border_theme, key, setting, value);
+        }
+        if let Some(setting) = key.strip_prefix("background_") {
+            return set_theme(&mut self.background_theme, key, setting, value);
+        }
+        match key {
+            "wobbly_grid_width" => self.wobbly_grid_width = parse_value::<usize>(key, value)?.max(2),
+            "wobbly_grid_height" => self.wobbly_grid_height = parse_value::<usize>(key, value)?.max(2),
+            "wobbly_spring_k" => self.wobbly_spring_k = parse_value(key, value)?,
+            "wobbly_friction" => self.wobbly_friction = parse_value(key, value)?,
+            // sets every animation at once, the specific keys below can override it afterwards
+            "animation_curve" => {
+                let curve = parse_curve(key, value)?;
+                for settings in self.all_animation_settings() {
+                    settings.curve = curve;
+                }
+            }
+            "animation_duration" => {
+                let duration_ms = parse_value(key, value)?;
+                for settings in self.all_animation_settings() {
+                    settings.duration_ms = duration_ms;
+                }
+            }
+            "map_animation_curve" => self.map_animation.curve = parse_curve(key, value)?,
+            "map_animation_duration" => self.map_animation.duration_ms = parse_value(key, value)?,
+            "unmap_animation_curve" => self.unmap_animation.curve = parse_curve(key, value)?,
+            "unmap_animation_duration" => self.unmap_animation.duration_ms = parse_value(key, value)?,
+            "destroy_animation_curve" => self.destroy_animation.curve = parse_curve(key, value)?,
+            "destroy_animation_duration" => self.destroy_animation.duration_ms = parse_value(key, value)?,
+            "minimize_animation_curve" => self.minimize_animation.curve = parse_curve(key, value)?,
+            "minimize_animation_duration" => self.minimize_animation.duration_ms = parse_value(key, value)?,
+            "restore_animation_curve" => self.restore_animation.curve = parse_curve(key, value)?,
+            "restore_animation_duration" => self.restore_animation.duration_ms = parse_value(key, value)?,
+            "workspace_animation" => self.workspace_animation_style = TransitionStyle::parse(value)
+                .ok_or_else(|| format!("invalid value for {}: {} (expected slide, cube, fade or none)", key, value))?,
+            "workspace_animation_curve" => self.workspace_animation.curve = parse_curve(key, value)?,
+            "workspace_animation_duration" => self.workspace_animation.duration_ms = parse_value(key, value)?,
+            "inactive_opacity" => self.inactive_opacity = parse_value::<f32>(key, value)?.clamp(0.0, 1.0),
+            "fade_step" => self.fade_step = parse_value::<f32>(key, value)?.clamp(0.001, 1.0),
+            "shadows" => self.shadows = parse_value(key, value)?,
+            "shadow_radius" => self.shadow_radius = parse_value(key, value)?,
+            "shadow_offset_x" => self.shadow_offset_x = parse_value(key, value)?,
+            "shadow_offset_y" => self.shadow_offset_y = parse_value(key, value)?,
+            "shadow_color" => self.shadow_color = parse_color(key, value)?,
+            "shadow_opacity" => self.shadow_opacity = parse_value::<f32>(key, value)?.clamp(0.0, 1.0),
+            "shadow_exclude" => self.shadow_exclude = parse_window_types(key, value)?,
+            "blur" => self.blur = parse_value(key, value)?,
+            "blur_passes" => self.blur_passes = parse_value(key, value)?,
+            "corner_radius" => self.corner_radius = parse_value(key, value)?,
+            "workspaces" => self.workspaces = parse_value::<usize>(key, value)?.max(1),
+            "layout" => self.layout = parse_layout(key, value)?,
+            "gap" => self.tiling.gap = parse_value::<i32>(key, value)?.max(0),
+            "outer_gap" => self.tiling.outer_gap = parse_value(key, value)?,
+            "master_ratio" => self.tiling.master_ratio = parse_value::<f32>(key, value)?.clamp(0.05, 0.95),
+            "master_count" => self.tiling.master_count = parse_value(key, value)?,
+            "snap_threshold" => self.snap_threshold = parse_value::<i32>(key, value)?.max(0),
+            "edge_tiling" => self.edge_tiling = parse_value(key, value)?,
+            "unredirect_fullscreen" => self.unredirect_fullscreen = parse_value(key, value)?,
+            "workspace_names" => self.workspace_names = value.split(',').map(|name| name.trim().to_string()).collect(),
+            "wallpaper" => self.wallpaper = expand_path(value),
+            "wallpaper_slideshow" => self.wallpaper_slideshow = Some(expand_path(value)),
+            "wallpaper_interval" => self.wallpaper_interval = parse_value::<u64>(key, value)?.max(1),
+            "wallpaper_fade" => self.wallpaper_fade_ms = parse_value(key, value)?,
+            "wallpaper_mode" => self.wallpaper_mode = WallpaperMode::parse(value)
+                .ok_or_else(|| format!("invalid value for {}: {} (expected fill, fit, stretch, centre or tile)", key, value))?,
+            "wallpaper_letterbox_color" => self.wallpaper_letterbox_color = parse_color(key, value)?,
+            "post_process_shader" => self.post_process_shader = Some(self.add_shader(value)),
+            "window_shader" => self.window_shader = Some(self.add_shader(value)),
+            "inactive_shader" => self.inactive_shader = Some(self.add_shader(value)),
+            _ => return Err(format!("unknown key {}", key)),
+        }
+        Ok(())
+    }
+
+    // finds or adds a shader file, returning its index in self.shaders
+    fn add_shader(&mut self, value: &str) -> usize {
+        let path = expand_path(value);
+        if let Some(index) = self.shaders.iter().position(|shader| *shader == path) {
+            return index;
+        }
+        self.shaders.push(path);
+        self.shaders.len() - 1
+    }
+
+    // index into layout::builtin() for the layout workspace n (from 0) starts with
+    pub fn workspace_layout(&self, workspace: usize) -> usize {
+        self.workspace_layouts.get(&workspace).copied().unwrap_or(self.layout)
+    }
+
+    // what pagers should call workspace n (from 0)
+    pub fn workspace_name(&self, workspace: usize) -> String {
+        match self.workspace_names.get(workspace) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => (workspace + 1).to_string(),
+        }
+    }
+
+    fn all_animation_settings(&mut self) -> [&mut AnimationSettings; 6] {
+        [&mut self.map_animation, &mut self.unmap_animation, &mut self.destroy_animation,
+         &mut self.minimize_animation, &mut self.restore_animation, &mut self.workspace_animation]
+    }
+}
@@ -0,0 +1,139 @@
+// when to draw the next frame, and how long the last one took to reach the screen. GLX_EXT_swap_control makes swaps
+// wait for vblank so the overlay never tears, GLX_OML_sync_control does the same and also tells us exactly when each
+// frame went up. with neither, a timer paces frames at the refresh rate instead
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::c_int;
+use std::time::{Duration, Instant};
+use libsex::bindings::*;
+
+type SwapIntervalExt = unsafe extern "C" fn(*mut Display, GLXDrawable, c_int);
+type SwapBuffersMscOml = unsafe extern "C" fn(*mut Display, GLXDrawable, i64, i64, i64) -> i64;
+type WaitForSbcOml = unsafe extern "C" fn(*mut Display, GLXDrawable, i64, *mut i64, *mut i64, *mut i64) -> Bool;
+
+// dt is counted in frames at this rate, which is what the physics were tuned for
+const BASE_RATE: f64 = 60.0;
+// after a long pause (nothing drawn while a window had the screen to itself, say) don't try to catch up on all of it
+const MAX_DT: f32 = 4.0;
+
+struct OmlSync {
+    swap_buffers_msc: SwapBuffersMscOml,
+    wait_for_sbc: WaitForSbcOml,
+}
+
+pub struct FrameClock {
+    display: *mut Display,
+    drawable: GLXDrawable,
+    vsync: bool, // swaps wait for vblank, so they do the pacing
+    oml: Option<OmlSync>,
+    interval: Duration, // one refresh of the fastest monitor
+    last_present: Instant, // when the last frame went up, as far as we can tell
+    last_ust: Option<i64>, // the same from oml, in microseconds
+    dt: f32,
+    start: Instant,
+}
+
+// a glx function, if the driver says it has the extension it's from
+unsafe fn load<F>(extensions: &str, extension: &str, name: &[u8]) -> Option<F> {
+    if !extensions.split(' ').any(|supported| supported == extension) {
+        return None;
+    }
+    let address = glXGetProcAddress(name.as_ptr() as *const GLubyte)?;
+    Some(mem::transmute_copy(&address))
+}
+
+impl FrameClock {
+    // drawable is the overlay, which has to have the gl context current
+    pub fn new(display: *mut Display, screen_number: c_int, drawable: GLXDrawable, refresh_rates: &[f64]) -> Self {
+        let extensions = unsafe {
+            let extensions = glXQueryExtensionsString(display, screen_number);
+            if extensions.is_null() { String::new() } else { CStr::from_ptr(extensions).to_string_lossy().into_owned() }
+        };
+        let swap_interval: Option<SwapIntervalExt> = unsafe { load(&extensions, "GLX_EXT_swap_control", b"glXSwapIntervalEXT\0") };
+        let oml = unsafe {
+            match (load(&extensions, "GLX_OML_sync_control", b"glXSwapBuffersMscOML\0"),
+                   load(&extensions, "GLX_OML_sync_control", b"glXWaitForSbcOML\0")) {
+                (Some(swap_buffers_msc), Some(wait_for_sbc)) => Some(OmlSync { swap_buffers_msc, wait_for_sbc }),
+                _ => None,
+            }
+        };
+        if let Some(swap_interval) = swap_interval {
+            unsafe {
+                swap_interval(display, drawable, 1);
+            }
+        }
+        let vsync = swap_interval.is_some() || oml.is_some();
+        println!("frame timing: {}", match (swap_interval.is_some(), oml.is_some()) {
+            (_, true) => "vsync with presentation times (GLX_OML_sync_control)",
+            (true, false) => "vsync (GLX_EXT_swap_control)",
+            (false, false) => "timer, frames might tear",
+        });
+        let mut clock = FrameClock {
+            display,
+            drawable,
+            vsync,
+            oml,
+            interval: Duration::ZERO,
+            last_present: Instant::now(),
+            last_ust: None,
+            dt: 1.0,
+            start: Instant::now(),
+        };
+        clock.set_refresh_rates(refresh_rates);
+        clock
+    }
+
+    // every monitor is on the one overlay, which can only be synced to one of them, so frames come as often as the fastest
+    // monitor wants them and the slower ones just skip some
+    pub fn set_refresh_rates(&mut self, refresh_rates: &[f64]) {
+        let fastest = refresh_rates.iter().copied().filter(|rate| rate.is_finite() && *rate > 0.0).fold(0.0, f64::max);
+        let rate = if fastest > 0.0 { fastest } else { BASE_RATE };
+        self.interval = Duration::from_secs_f64(1.0 / rate);
+    }
+
+    // with vsync the swap itself waits for the right moment, otherwise a frame is due once the last one's had its refresh
+    pub fn frame_due(&self) -> bool {
+        self.vsync || self.last_present.elapsed() >= self.interval
+    }
+
+    // how long the last frame was on screen for, in 60hz frames
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    // 60hz ticks since startup, however often frames actually get drawn
+    pub fn ticks(&self) -> u64 {
+        (self.start.elapsed().as_secs_f64() * BASE_RATE) as u64
+    }
+
+    // puts the frame on screen and works out when it got there
+    pub fn swap(&mut self) {
+        let presented_after = unsafe {
+            match &self.oml {
+                Some(oml) => {
+                    // swap on the next vblank, then wait until it's actually happened to hear when that was
+                    let sbc = (oml.swap_buffers_msc)(self.display, self.drawable, 0, 1, 0);
+                    let (mut ust, mut msc, mut done_sbc) = (0i64, 0i64, 0i64);
+                    if sbc >= 0 && (oml.wait_for_sbc)(self.display, self.drawable, sbc, &mut ust, &mut msc, &mut done_sbc) != 0 {
+                        let since_last = self.last_ust.map(|last| (ust - last) as f64 / 1_000_000.0);
+                        self.last_ust = Some(ust);
+                        since_last
+                    } else {
+                        None
+                    }
+                }
+                None => {
+                    glXSwapBuffers(self.display, self.drawable);
+                    if self.vsync {
+                        // the swap is only queued up until something waits for it, and we want to know when it's done
+                        glFinish();
+                    }
+                    None
+                }
+            }
+        };
+        let elapsed = presented_after.unwrap_or_else(|| self.last_present.elapsed().as_secs_f64());
+        self.last_present = Instant::now();
+        self.dt = ((elapsed * BASE_RATE) as f32).clamp(0.0, MAX_DT);
+    }
+}
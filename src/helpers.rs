@@ -17,12 +17,21 @@ pub fn rgba_to_bgra(rgba_array: &[u8]) -> Vec<u8> {
 
 }
 
-use std::ffi::{c_void, CStr};
-use std::os::raw::{c_int, c_uint, c_ulong};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong};
 use std::{mem, ptr};
 use std::ptr::{null, null_mut};
 use libsex::bindings::*;
 use crate::CumWindow;
+use crate::types::WindowType;
+use crate::layout::Rect;
+use crate::physics::WobblyMesh;
+use crate::animation::Transform;
+use crate::shadow::Shadow;
+use crate::blur::Blur;
+use crate::renderer::{DrawParams, Fill, Renderer, VERTEX_SIZE};
 
 pub fn allow_input_passthrough(display: *mut Display, win: Window, x: i16, y: i16) {
     unsafe {
@@ -150,203 +159,649 @@ pub fn redraw_desktop(display: *mut Display, picture: Picture, desktop: Picture,
     }
 }
 
-pub fn draw_x_window(window: CumWindow, draw_frame: bool, display: *mut Display, shader_program: GLuint, force_fullscreen: bool, src_width: u32, src_height: u32, border_r: u32, border_g: u32, border_b: u32) {
-    // now unsafe time!
-    unsafe {
+// maps a spot in the frame (0.0 - 1.0 across it) to the screen, following the mesh if there is one
+fn frame_position(mesh: Option<&WobblyMesh>, rect: (f32, f32, f32, f32), u: f32, v: f32) -> (f32, f32) {
+    match mesh {
+        Some(mesh) => mesh.position_at(u, v),
+        None => (rect.0 + u * rect.2, rect.1 + v * rect.3),
+    }
+}
+
+// samples the (possibly wobbling) rectangle between (u0, v0) and (u1, v1) into a grid of vertices
+fn mesh_vertices(mesh: Option<&WobblyMesh>, rect: (f32, f32, f32, f32), uv: (f32, f32, f32, f32), cols: usize, rows: usize) -> Vec<f32> {
+    let mut vertices = Vec::with_capacity(cols * rows * VERTEX_SIZE);
+    for row in 0..rows {
+        for col in 0..cols {
+            let s = col as f32 / (cols - 1) as f32;
+            let t = row as f32 / (rows - 1) as f32;
+            let (x, y) = frame_position(mesh, rect, uv.0 + (uv.2 - uv.0) * s, uv.1 + (uv.3 - uv.1) * t);
+            vertices.extend_from_slice(&[x, y, s, t, s, t]);
+        }
+    }
+    vertices
+}
+
+// two triangles for every cell of a cols x rows grid of vertices
+fn grid_indices(cols: usize, rows: usize) -> Vec<u32> {
+    let mut indices = Vec::with_capacity((cols - 1) * (rows - 1) * 6);
+    for row in 0..rows - 1 {
+        for col in 0..cols - 1 {
+            let top_left = (row * cols + col) as u32;
+            let top_right = top_left + 1;
+            let bottom_left = top_left + cols as u32;
+            let bottom_right = bottom_left + 1;
+            indices.extend_from_slice(&[top_left, top_right, bottom_left, top_right, bottom_right, bottom_left]);
+        }
+    }
+    indices
+}
+
+// walks around the edge of the (u0, v0) - (u1, v1) part of the frame, which is size pixels big, with rounded corners
+fn outline_vertices(mesh: Option<&WobblyMesh>, rect: (f32, f32, f32, f32), uv: (f32, f32, f32, f32), size: (f32, f32), radius: f32, edge_segments: usize) -> Vec<f32> {
+    let radius = radius.min(size.0 / 2.0).min(size.1 / 2.0).max(0.0);
+    let corner_segments = if radius > 0.0 { 8 } else { 0 };
+    // corner centres and the angle each arc starts at, clockwise from the top left
+    let corners = [
+        (radius, radius, std::f32::consts::PI),
+        (size.0 - radius, radius, std::f32::consts::PI * 1.5),
+        (size.0 - radius, size.1 - radius, 0.0),
+        (radius, size.1 - radius, std::f32::consts::PI * 0.5),
+    ];
+    let mut points: Vec<(f32, f32)> = Vec::new();
+    for (i, (centre_x, centre_y, start)) in corners.iter().enumerate() {
+        for segment in 0..=corner_segments {
+            let angle = start + std::f32::consts::FRAC_PI_2 * segment as f32 / corner_segments.max(1) as f32;
+            points.push((centre_x + angle.cos() * radius, centre_y + angle.sin() * radius));
+        }
+        // split the straight edge up to the next corner so it can bend with the mesh
+        let (next_x, next_y, next_start) = corners[(i + 1) % 4];
+        let (from_x, from_y) = *points.last().unwrap();
+        let (to_x, to_y) = (next_x + next_start.cos() * radius, next_y + next_start.sin() * radius);
+        for segment in 1..edge_segments {
+            let t = segment as f32 / edge_segments as f32;
+            points.push((from_x + (to_x - from_x) * t, from_y + (to_y - from_y) * t));
+        }
+    }
+
+    let mut vertices = Vec::with_capacity(points.len() * VERTEX_SIZE);
+    for (x, y) in points {
+        let s = x / size.0;
+        let t = y / size.1;
+        let (x, y) = frame_position(mesh, rect, uv.0 + (uv.2 - uv.0) * s, uv.1 + (uv.3 - uv.1) * t);
+        vertices.extend_from_slice(&[x, y, s, t, s, t]);
+    }
+    vertices
+}
+
+// indices that join up the points of an outline into a closed loop of lines
+fn loop_indices(points: usize) -> Vec<u32> {
+    (0..points as u32).flat_map(|i| [i, (i + 1) % points as u32]).collect()
+}
 
+// moves vertices to where the animation transform puts them, scaling around the centre of the frame
+fn apply_transform(vertices: &mut [f32], transform: Transform, centre: (f32, f32)) {
+    for vertex in vertices.chunks_mut(VERTEX_SIZE) {
+        vertex[0] = centre.0 + transform.offset_x + (vertex[0] - centre.0) * transform.scale;
+        vertex[1] = centre.1 + transform.offset_y + (vertex[1] - centre.1) * transform.scale;
+    }
+}
 
-        let window_id = window.window_id;
-        let frame_id = window.frame_id;
-        
-        let mut window_x = window.x as f64;
-        let mut window_y = window.y;
+// textures that hold the last picture we got of a window, kept around so closing windows can still be drawn
+#[derive(Clone, Copy, Default)]
+pub struct WindowTextures {
+    pub frame: GLuint,
+    pub client: GLuint,
+}
 
-        let frame_x = (window_x - 10.0) as f32;
-        let frame_y = (window_y - 20) as f32;
-        let frame_width = (window.width + 20) as f32;
-        let frame_height = (window.height + 25) as f32;
+impl WindowTextures {
+    pub fn delete(&mut self) {
+        unsafe {
+            if self.frame != 0 {
+                glDeleteTextures(1, &self.frame);
+            }
+            if self.client != 0 {
+                glDeleteTextures(1, &self.client);
+            }
+        }
+        self.frame = 0;
+        self.client = 0;
+    }
+}
 
-        //println!("{} {}", width, height);
+unsafe fn upload_ximage(texture: &mut GLuint, xim: *mut XImage, width: i32, height: i32, has_alpha: bool) {
+    if *texture == 0 {
+        glGenTextures(1, texture);
+    }
+    glBindTexture(GL_TEXTURE_2D, *texture);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+    glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+    // windows without an alpha channel have garbage in the padding byte, so throw it away
+    let internal_format = if has_alpha { GL_RGBA8 } else { GL_RGB8 };
+    glTexImage2D(GL_TEXTURE_2D, 0,
+                 internal_format as GLint, width, height,
+                 0, GL_BGRA,
+                 GL_UNSIGNED_BYTE, (*xim).data as *mut c_void);
+}
 
-        let frame_xim;
-        let xim = XGetImage(display, window_id,
+// copies the current contents of the window (and its frame) into its textures; returns false if the window can't be read
+pub fn update_window_textures(window: CumWindow, textures: &mut WindowTextures, draw_frame: bool, display: *mut Display) -> bool {
+    // shaded windows are only a title bar and their client isn't mapped, so there's only the frame to get
+    if window.state.shaded {
+        unsafe {
+            let frame_xim = XGetImage(display, window.frame_id,
+                                      0, 0,
+                                      window.width as c_uint + 20, window.frame_height() as c_uint, XAllPlanes(), ZPixmap as c_int);
+            XSync(display, 0);
+            if frame_xim.is_null() {
+                println!("could not get xim for frame {}", window.frame_id);
+                return false;
+            }
+            upload_ximage(&mut textures.frame, frame_xim, window.width as i32 + 20, window.frame_height() as i32, false);
+            XDestroyImage(frame_xim);
+        }
+        return true;
+    }
+    unsafe {
+        let xim = XGetImage(display, window.window_id,
                             0, 0,
                             window.width as c_uint, window.height as c_uint, XAllPlanes(), ZPixmap as c_int);
+        let mut frame_xim: *mut XImage = null_mut();
         if draw_frame {
-            frame_xim = XGetImage(display, frame_id,
+            frame_xim = XGetImage(display, window.frame_id,
                                   0, 0,
                                   window.width as c_uint + 20, window.height as c_uint + 25, XAllPlanes(), ZPixmap as c_int);
-        } else {
-            frame_xim = xim;
         }
         XSync(display, 0);
 
         if xim.is_null() {
-            println!("could not get xim for window {}", window_id);
-            XFree(xim as *mut c_void);
-            return;
+            println!("could not get xim for window {}", window.window_id);
+            XDestroyImage(frame_xim);
+            return false;
         }
 
-        let border_width = 1 as GLfloat;
-        //glPushAttrib(GL_CURRENT_BIT);
-
-        if draw_frame && !force_fullscreen {
-            glLineWidth(border_width);
-
-            glDisable(GL_TEXTURE_2D);
-            glBegin(GL_LINES);
-            glColor3f(border_r as f32 / 255.0, border_g as f32 / 255.0, border_b as f32 / 255.0);
-
-            // top left to bottom left
-            glVertex2f(frame_x, frame_y);
-            glVertex2f(frame_x + window.velocity.x_speed as f32, frame_y + frame_height);
-
-            // top left to top right
-            glVertex2f(frame_x - (border_width / 2.0), frame_y);
-            glVertex2f(frame_x + frame_width + (border_width / 2.0), frame_y);
-
-            // top right to bottom right
-            glVertex2f(frame_x + frame_width, frame_y);
-            glVertex2f(frame_x + frame_width + window.velocity.x_speed as f32, frame_y + frame_height);
-
-            // bottom right to bottom left
-            glVertex2f(frame_x + frame_width + (border_width / 2.0) + window.velocity.x_speed as f32, frame_y + frame_height);
-            glVertex2f(frame_x - (border_width / 2.0) + window.velocity.x_speed as f32, frame_y + frame_height);
-
-            glEnd();
+        if !frame_xim.is_null() {
+            upload_ximage(&mut textures.frame, frame_xim, window.width as i32 + 20, window.height as i32 + 25, false);
         }
+        upload_ximage(&mut textures.client, xim, window.width as i32, window.height as i32, window.has_alpha);
 
-        let mut texture: GLuint = 0;
-        glEnable(GL_TEXTURE_2D);
-        let mut frame_texture: GLuint = 0;
-        if draw_frame {
-            // create another texture for the frame
-            glGenTextures(1, &mut frame_texture);
-            glBindTexture(GL_TEXTURE_2D, frame_texture);
-            let loc = glGetUniformLocation(shader_program, "tex".as_ptr() as *const i8);
-            glUniform1i(loc, 0);
+        XDestroyImage(xim);
+        XDestroyImage(frame_xim);
+    }
+    true
+}
 
-            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
-            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
-            glTexEnvf(GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE, GL_REPLACE as GLfloat);
+pub fn draw_x_window(window: CumWindow, mesh: Option<&WobblyMesh>, renderer: &mut Renderer, textures: &mut WindowTextures, transform: Transform, shadow: Option<Shadow>, blur: Option<(&Blur, &[(i32, i32, i32, i32)])>, corner_radius: f32, shader: GLuint, focused: bool, draw_frame: bool, display: *mut Display, force_fullscreen: bool, border: Fill) {
+    if !update_window_textures(window, textures, draw_frame, display) {
+        return;
+    }
+    draw_window_textures(window, mesh, renderer, textures, transform, shadow, blur, corner_radius, shader, focused, draw_frame, force_fullscreen, border);
+}
 
-            glTexImage2D(GL_TEXTURE_2D, 0,
-                         GL_RGBA8 as GLint, window.width as i32 + 20, window.height as i32 + 25,
-                         0, GL_BGRA,
-                         GL_UNSIGNED_BYTE, (*frame_xim).data as *mut c_void);
+// draws whatever is in the window's textures, without asking x for anything; used for windows that are already gone
+pub fn draw_window_textures(window: CumWindow, mesh: Option<&WobblyMesh>, renderer: &mut Renderer, textures: &WindowTextures, transform: Transform, shadow: Option<Shadow>, blur: Option<(&Blur, &[(i32, i32, i32, i32)])>, corner_radius: f32, shader: GLuint, focused: bool, draw_frame: bool, force_fullscreen: bool, border: Fill) {
+    // the mesh always covers the frame, the client sits inside it
+    let frame_rect = if force_fullscreen {
+        (window.x as f32, window.y as f32, window.width as f32, window.height as f32)
+    } else {
+        ((window.x - 10) as f32, (window.y - 20) as f32, (window.width + 20) as f32, window.frame_height() as f32)
+    };
+    let client_uv = if force_fullscreen {
+        (0.0, 0.0, 1.0, 1.0)
+    } else {
+        (10.0 / frame_rect.2, 20.0 / frame_rect.3,
+         (10.0 + window.width as f32) / frame_rect.2, (20.0 + window.height as f32) / frame_rect.3)
+    };
+    let frame_uv = (0.0, 0.0, 1.0, 1.0);
+    let frame_size = (frame_rect.2, frame_rect.3);
+    let client_size = (window.width as f32, window.height as f32);
+    // nothing to round if the window fills its monitor
+    let corner_radius = if force_fullscreen { 0.0 } else { corner_radius };
+
+    // sample the client at twice the mesh resolution, since its edges don't line up with the control points
+    let (cols, rows) = match mesh {
+        Some(mesh) => (mesh.cols, mesh.rows),
+        None => (2, 2),
+    };
+    let (client_cols, client_rows) = match mesh {
+        Some(mesh) => (mesh.cols * 2 - 1, mesh.rows * 2 - 1),
+        None => (2, 2),
+    };
 
-            glBegin(GL_QUADS);
-            glTexCoord2d(1.0, 0.0); // top right of the drawing area
-            glVertex2d((frame_x as i32 + frame_width as i32) as GLdouble, frame_y as GLdouble);
+    // scale and move around the centre of the frame for animations
+    let centre = (frame_rect.0 + frame_rect.2 / 2.0, frame_rect.1 + frame_rect.3 / 2.0);
+    let mut frame_vertices = mesh_vertices(mesh, frame_rect, frame_uv, cols, rows);
+    let mut client_vertices = mesh_vertices(mesh, frame_rect, client_uv, client_cols, client_rows);
+    apply_transform(&mut frame_vertices, transform, centre);
+    apply_transform(&mut client_vertices, transform, centre);
+    let frame_indices = grid_indices(cols, rows);
+    let client_indices = grid_indices(client_cols, client_rows);
+
+    // the window's own opacity (fading, _NET_WM_WINDOW_OPACITY, rules) times whatever the animation wants
+    let opacity = window.opacity * transform.opacity;
+
+    if let Some(shadow) = shadow {
+        // the shadow texture is the frame plus the radius on every side, moved by the offset, and wobbles along with it;
+        // its corners are already rounded when it's generated
+        let shadow_uv = ((shadow.offset_x - shadow.radius) / frame_rect.2, (shadow.offset_y - shadow.radius) / frame_rect.3,
+                         1.0 + (shadow.offset_x + shadow.radius) / frame_rect.2, 1.0 + (shadow.offset_y + shadow.radius) / frame_rect.3);
+        let mut shadow_vertices = mesh_vertices(mesh, frame_rect, shadow_uv, client_cols, client_rows);
+        apply_transform(&mut shadow_vertices, transform, centre);
+        let color = [shadow.color[0], shadow.color[1], shadow.color[2], shadow.opacity];
+        renderer.queue(DrawParams::new(Fill::Mask(shadow.texture, color), opacity, frame_size, 0.0),
+                       GL_TRIANGLES, &shadow_vertices, &client_indices);
+    }
 
-            glTexCoord2d(1.0, 1.0); // bottom right of the drawing area
-            glVertex2d((frame_x as i32 + frame_width as i32) as GLdouble + window.velocity.x_speed, (frame_y as i32 + frame_height as i32) as GLdouble);
+    // blur what's behind the window (its shadow included) before any of it gets drawn over it: the parts of the client
+    // it asked for with _KDE_NET_WM_BLUR_BEHIND_REGION, or the whole frame if it didn't pick any
+    if let Some((blur, rects)) = blur {
+        let parts: Vec<(f32, f32, f32, f32)> = if rects.is_empty() {
+            vec![frame_uv]
+        } else {
+            // the rectangles are in client pixels, and can't reach outside the client
+            rects.iter().map(|&(x, y, width, height)| {
+                let to_u = |x: i32| client_uv.0 + x.clamp(0, window.width as i32) as f32 / frame_rect.2;
+                let to_v = |y: i32| client_uv.1 + y.clamp(0, window.height as i32) as f32 / frame_rect.3;
+                (to_u(x), to_v(y), to_u(x + width), to_v(y + height))
+            }).filter(|uv| uv.2 > uv.0 && uv.3 > uv.1).collect()
+        };
+        for uv in parts {
+            let whole_frame = uv == frame_uv;
+            let mut part_vertices = Vec::new();
+            if !whole_frame {
+                part_vertices = mesh_vertices(mesh, frame_rect, uv, cols, rows);
+                apply_transform(&mut part_vertices, transform, centre);
+            }
+            let part_vertices = if whole_frame { &frame_vertices } else { &part_vertices };
+            let (mut left, mut top, mut right, mut bottom) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+            for vertex in part_vertices.chunks(VERTEX_SIZE) {
+                left = left.min(vertex[0]);
+                top = top.min(vertex[1]);
+                right = right.max(vertex[0]);
+                bottom = bottom.max(vertex[1]);
+            }
+            let region = blur.blur_region(renderer, left.floor() as i32, top.floor() as i32,
+                                          (right - left).ceil() as i32 + 1, (bottom - top).ceil() as i32 + 1);
+            if let Some((texture, origin_x, origin_y)) = region {
+                let (texture_width, texture_height) = blur.size();
+                let vertices: Vec<f32> = part_vertices.chunks(VERTEX_SIZE).flat_map(|vertex| {
+                    [vertex[0], vertex[1], (vertex[0] - origin_x as f32) / texture_width as f32, (origin_y as f32 - vertex[1]) / texture_height as f32,
+                     vertex[4], vertex[5]]
+                }).collect();
+                // only the whole frame has the window's corners to round
+                let (size, radius) = if whole_frame {
+                    (frame_size, corner_radius)
+                } else {
+                    (((uv.2 - uv.0) * frame_rect.2, (uv.3 - uv.1) * frame_rect.3), 0.0)
+                };
+                renderer.queue(DrawParams::new(Fill::Texture(texture), opacity, size, radius),
+                               GL_TRIANGLES, &vertices, &frame_indices);
+            }
+        }
+    }
 
-            glTexCoord2d(0.0, 1.0); // bottom left of the drawing area
-            glVertex2d(frame_x as GLdouble + window.velocity.x_speed, (frame_y as i32 + frame_height as i32) as GLdouble);
+    // the borders are plain lines, rounded by following the same path as the corners
+    let edge_segments = cols.max(rows) * 2;
+    let border = DrawParams::new(border, opacity, frame_size, 0.0);
+    if draw_frame && !force_fullscreen {
+        let mut outline = outline_vertices(mesh, frame_rect, frame_uv, frame_size, corner_radius, edge_segments);
+        apply_transform(&mut outline, transform, centre);
+        renderer.queue(border, GL_LINES, &outline, &loop_indices(outline.len() / VERTEX_SIZE));
+    }
 
-            glTexCoord2d(0.0, 0.0); // top left of the drawing area
-            glVertex2d(frame_x as GLdouble, frame_y as GLdouble);
+    if draw_frame && textures.frame != 0 {
+        renderer.queue(DrawParams::new(Fill::Texture(textures.frame), opacity, frame_size, corner_radius),
+                       GL_TRIANGLES, &frame_vertices, &frame_indices);
+    }
+    // a shaded window's client is rolled up out of sight
+    if window.state.shaded {
+        return;
+    }
+    if draw_frame && !force_fullscreen {
+        let mut outline = outline_vertices(mesh, frame_rect, client_uv, client_size, corner_radius, edge_segments);
+        apply_transform(&mut outline, transform, centre);
+        renderer.queue(border, GL_LINES, &outline, &loop_indices(outline.len() / VERTEX_SIZE));
+    }
 
-            glEnd();
+    let mut err = unsafe { glGetError() };
+    let care_about_errors = false; // printing the errors takes a lot of cpu time, disable unless debugging
+    if care_about_errors {
+        while err != GL_NO_ERROR {
+            if err != 1282 { // don't print this error because it shows up too much and i don't like it
+                println!("{}", err);
+            }
+            err = unsafe { glGetError() };
         }
-        if !force_fullscreen {
-            glLineWidth(border_width);
-
-            glDisable(GL_TEXTURE_2D);
-            glBegin(GL_LINES);
-            glColor3f(border_r as f32 / 255.0, border_g as f32 / 255.0, border_b as f32 / 255.0);
-
-            // top left to bottom left
-            glVertex2f(window_x as GLfloat, window_y as GLfloat);
-            glVertex2f(window_x as GLfloat + window.velocity.x_speed as f32, (window_y + window.height as i32) as GLfloat);
+    }
 
-            // top left to top right
-            glVertex2f(window_x as GLfloat, window_y as GLfloat);
-            glVertex2f((window_x + window.width as f64) as GLfloat, window_y as GLfloat);
+    // argb windows come from x with their colours already multiplied by alpha
+    let fill = if window.has_alpha { Fill::Premultiplied(textures.client) } else { Fill::Texture(textures.client) };
+    // custom shaders only ever apply to the client, the frame and borders always use the default one
+    let params = DrawParams { shader, focused, velocity: mesh.map_or(0.0, |mesh| mesh.x_velocity()), ..DrawParams::new(fill, opacity, client_size, corner_radius) };
+    renderer.queue(params, GL_TRIANGLES, &client_vertices, &client_indices);
+}
 
-            // top right to bottom right
-            glVertex2f((window_x + window.width as f64) as GLfloat, window_y as GLfloat);
-            glVertex2f((window_x + window.width as f64) as GLfloat + window.velocity.x_speed as f32, (window_y + window.height as i32) as GLfloat);
+thread_local! {
+    // every XInternAtom is a round trip to the server, so each atom only gets asked for once
+    static ATOMS: RefCell<HashMap<String, Atom>> = RefCell::new(HashMap::new());
+}
 
-            // bottom right to bottom left
-            glVertex2f((window_x + window.width as f64) as GLfloat + window.velocity.x_speed as f32, (window_y + window.height as i32) as GLfloat);
-            glVertex2f(window_x as GLfloat + window.velocity.x_speed as f32, (window_y + window.height as i32) as GLfloat);
+pub fn get_atom(display: *mut Display, name: &str) -> Atom {
+    if let Some(atom) = ATOMS.with(|atoms| atoms.borrow().get(name).copied()) {
+        return atom;
+    }
+    let c_name = CString::new(name).unwrap();
+    let atom = unsafe { XInternAtom(display, c_name.as_ptr(), 0) };
+    ATOMS.with(|atoms| atoms.borrow_mut().insert(name.to_string(), atom));
+    atom
+}
 
-            glEnd();
-            glEnable(GL_TEXTURE_2D);
+// interns a bunch of atoms in a single round trip, so get_atom never has to wait for them later
+pub fn cache_atoms(display: *mut Display, names: &[&str]) {
+    let c_names: Vec<CString> = names.iter().map(|name| CString::new(*name).unwrap()).collect();
+    let mut name_ptrs: Vec<*mut c_char> = c_names.iter().map(|name| name.as_ptr() as *mut c_char).collect();
+    let mut atoms: Vec<Atom> = vec![0; names.len()];
+    let status = unsafe { XInternAtoms(display, name_ptrs.as_mut_ptr(), name_ptrs.len() as c_int, 0, atoms.as_mut_ptr()) };
+    if status == 0 {
+        // leave them to get_atom, one at a time
+        return;
+    }
+    ATOMS.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        for (name, atom) in names.iter().zip(atoms) {
+            cache.insert(name.to_string(), atom);
         }
-        glGenTextures(1, &mut texture);
-        glBindTexture(GL_TEXTURE_2D, texture);
-        let loc = glGetUniformLocation(shader_program, "tex".as_ptr() as *const i8);
-        glUniform1i(loc, 0);
-
-        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
-        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
-        glTexEnvf(GL_TEXTURE_ENV, GL_TEXTURE_ENV_MODE, GL_REPLACE as GLfloat);
+    });
+}
 
-        glTexImage2D(GL_TEXTURE_2D, 0,
-                     GL_RGBA8 as GLint, window.width as i32, window.height as i32,
-                     0, GL_BGRA,
-                     GL_UNSIGNED_BYTE, (*xim).data as *mut c_void);
+pub fn get_atom_name(display: *mut Display, atom: Atom) -> Option<String> {
+    unsafe {
+        let name_ptr = XGetAtomName(display, atom);
+        if name_ptr.is_null() {
+            return None;
+        }
+        let name = CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+        XFree(name_ptr as *mut c_void);
+        Some(name)
+    }
+}
 
+// reads a list of 32-bit values (CARDINAL, ATOM, WINDOW...) from a window property
+pub fn get_cardinal_property(display: *mut Display, window: Window, property: &str) -> Option<Vec<u64>> {
+    let atom = get_atom(display, property);
+    unsafe {
+        let mut actual_type: Atom = 0;
+        let mut actual_format: c_int = 0;
+        let mut nitems: c_ulong = 0;
+        let mut bytes_after: c_ulong = 0;
+        let mut data: *mut c_uchar = null_mut();
+        let status = XGetWindowProperty(display, window, atom, 0, 1024, 0, AnyPropertyType as Atom,
+                                        &mut actual_type, &mut actual_format, &mut nitems, &mut bytes_after, &mut data);
+        if status != Success as c_int || data.is_null() {
+            return None;
+        }
+        if actual_format != 32 || nitems == 0 {
+            XFree(data as *mut c_void);
+            return None;
+        }
+        // format 32 properties come back as an array of longs, whatever size those are
+        let values = std::slice::from_raw_parts(data as *const c_ulong, nitems as usize)
+            .iter().map(|value| *value as u64).collect();
+        XFree(data as *mut c_void);
+        Some(values)
+    }
+}
 
-        let mut err = glGetError();
-        let care_about_errors = false; // printing the errors takes a lot of cpu time, disable unless debugging
-        if care_about_errors {
-            while err != GL_NO_ERROR {
-                if err != 1282 { // don't print this error because it shows up too much and i don't like it
-                    println!("{}", err);
+// the parts of a window it wants blurred behind it, from _KDE_NET_WM_BLUR_BEHIND_REGION (x, y, width, height
+// in client pixels, four values a rectangle), or None if it hasn't set it. an empty list means the whole window
+pub fn get_blur_region(display: *mut Display, window: Window) -> Option<Vec<(i32, i32, i32, i32)>> {
+    let atom = get_atom(display, "_KDE_NET_WM_BLUR_BEHIND_REGION");
+    unsafe {
+        let mut actual_type: Atom = 0;
+        let mut actual_format: c_int = 0;
+        let mut nitems: c_ulong = 0;
+        let mut bytes_after: c_ulong = 0;
+        let mut data: *mut c_uchar = null_mut();
+        let status = XGetWindowProperty(display, window, atom, 0, 1024, 0, AnyPropertyType as Atom,
+                                        &mut actual_type, &mut actual_format, &mut nitems, &mut bytes_after, &mut data);
+        if status != Success as c_int {
+            return None;
+        }
+        // unlike other properties, being set with nothing in it still means something here
+        let present = actual_type != 0;
+        let mut rects = Vec::new();
+        if !data.is_null() {
+            if actual_format == 32 {
+                let values = std::slice::from_raw_parts(data as *const c_ulong, nitems as usize);
+                for rect in values.chunks_exact(4) {
+                    rects.push((rect[0] as i32, rect[1] as i32, rect[2] as i32, rect[3] as i32));
                 }
-                err = glGetError();
             }
+            XFree(data as *mut c_void);
         }
+        if present { Some(rects) } else { None }
+    }
+}
 
-        if window.has_alpha {
-            glEnable(GL_BLEND);
-            glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
-        } else {
-            glDisable(GL_BLEND);
-        }
+// writes a list of 32-bit values to a window property, replacing whatever was there
+pub fn set_cardinal_property(display: *mut Display, window: Window, property: &str, property_type: &str, values: &[u64]) {
+    // same as reading, x wants format 32 data as longs
+    let data: Vec<c_ulong> = values.iter().map(|value| *value as c_ulong).collect();
+    unsafe {
+        XChangeProperty(display, window, get_atom(display, property), get_atom(display, property_type), 32,
+                        PropModeReplace as c_int, data.as_ptr() as *const c_uchar, data.len() as c_int);
+    }
+}
 
-        glBegin(GL_QUADS);
+// writes a list of strings as UTF8_STRING, each one ending with a null (like _NET_DESKTOP_NAMES wants)
+pub fn set_utf8_list_property(display: *mut Display, window: Window, property: &str, values: &[String]) {
+    let mut data: Vec<u8> = Vec::new();
+    for value in values {
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+    }
+    unsafe {
+        XChangeProperty(display, window, get_atom(display, property), get_atom(display, "UTF8_STRING"), 8,
+                        PropModeReplace as c_int, data.as_ptr(), data.len() as c_int);
+    }
+}
 
-        if !force_fullscreen {
-            glTexCoord2d(1.0, 0.0); // top right of the drawing area
-            glVertex2d((window_x as i32 + window.width as i32) as GLdouble, window_y as GLdouble);
+// writes a single UTF8_STRING, without a null at the end (like _NET_WM_NAME wants)
+pub fn set_utf8_property(display: *mut Display, window: Window, property: &str, value: &str) {
+    unsafe {
+        XChangeProperty(display, window, get_atom(display, property), get_atom(display, "UTF8_STRING"), 8,
+                        PropModeReplace as c_int, value.as_ptr(), value.len() as c_int);
+    }
+}
 
-            glTexCoord2d(1.0, 1.0); // bottom right of the drawing area
-            glVertex2d((window_x + window.velocity.x_speed + window.width as f64) as GLdouble, (window_y as i32 + window.height as i32) as GLdouble);
+// _NET_WM_DESKTOP values: 0xffffffff means the window is on every workspace, which we call None
+pub fn workspace_from_cardinal(value: u64) -> Option<usize> {
+    // some clients send -1 as a 64-bit long, so only look at the bottom 32 bits
+    match value & 0xffffffff {
+        0xffffffff => None,
+        workspace => Some(workspace as usize),
+    }
+}
 
-            glTexCoord2d(0.0, 1.0); // bottom left of the drawing area
+pub fn workspace_to_cardinal(workspace: Option<usize>) -> u64 {
+    workspace.map_or(0xffffffff, |workspace| workspace as u64)
+}
 
-            glVertex2d(window_x as GLdouble + window.velocity.x_speed, (window_y as i32 + window.height as i32) as GLdouble);
+// instance and class names from WM_CLASS, empty if the window doesn't have one
+pub fn get_window_class(display: *mut Display, window: Window) -> (String, String) {
+    unsafe {
+        let mut hint = XClassHint { res_name: null_mut(), res_class: null_mut() };
+        if XGetClassHint(display, window, &mut hint) == 0 {
+            return (String::new(), String::new());
+        }
+        let mut names = (String::new(), String::new());
+        if !hint.res_name.is_null() {
+            names.0 = CStr::from_ptr(hint.res_name).to_string_lossy().into_owned();
+            XFree(hint.res_name as *mut c_void);
+        }
+        if !hint.res_class.is_null() {
+            names.1 = CStr::from_ptr(hint.res_class).to_string_lossy().into_owned();
+            XFree(hint.res_class as *mut c_void);
+        }
+        names
+    }
+}
 
-            glTexCoord2d(0.0, 0.0); // top left of the drawing area
-            glVertex2d(window_x as GLdouble, window_y as GLdouble);
-        } else { // use src_width and src_height to get the size of the fullscreen window
-            glTexCoord2d(1.0, 0.0); // top right of the drawing area
-            glVertex2d(src_width as GLdouble, 0.0);
+// _NET_WM_WINDOW_OPACITY goes from 0 to 0xffffffff, no property means fully opaque
+pub fn get_window_opacity(display: *mut Display, window: Window) -> f32 {
+    match get_cardinal_property(display, window, "_NET_WM_WINDOW_OPACITY") {
+        Some(values) => (values[0] & 0xffffffff) as f32 / 0xffffffffu32 as f32,
+        None => 1.0,
+    }
+}
 
-            glTexCoord2d(1.0, 1.0); // bottom right of the drawing area
-            glVertex2d(src_width as GLdouble, src_height as GLdouble);
+// the first type in _NET_WM_WINDOW_TYPE that we know about; windows without one are normal
+pub fn get_window_type(display: *mut Display, window: Window) -> WindowType {
+    let atoms = match get_cardinal_property(display, window, "_NET_WM_WINDOW_TYPE") {
+        Some(atoms) => atoms,
+        None => return WindowType::Normal,
+    };
+    for atom in atoms {
+        let name = match get_atom_name(display, atom as Atom) {
+            Some(name) => name,
+            None => continue,
+        };
+        if let Some(window_type) = WindowType::parse(&name) {
+            return window_type;
+        }
+    }
+    WindowType::Normal
+}
 
-            glTexCoord2d(0.0, 1.0); // bottom left of the drawing area
-            glVertex2d(0.0, src_height as GLdouble);
+// the window this one is a dialog (or some other kind of helper) for, from WM_TRANSIENT_FOR.
+// this can be the root window, which means it belongs to its whole window group
+pub fn get_transient_for(display: *mut Display, window: Window) -> Option<Window> {
+    let mut parent: Window = 0;
+    let status = unsafe { XGetTransientForHint(display, window, &mut parent) };
+    if status == 0 || parent == 0 || parent == window {
+        return None;
+    }
+    Some(parent)
+}
 
-            glTexCoord2d(0.0, 0.0); // top left of the drawing area
-            glVertex2d(0.0, 0.0);
+// the leader of the window's group, from WM_HINTS; every window an application opens usually shares one
+pub fn get_window_group(display: *mut Display, window: Window) -> Option<Window> {
+    unsafe {
+        let hints = XGetWMHints(display, window);
+        if hints.is_null() {
+            return None;
         }
+        let group = if (*hints).flags & WindowGroupHint as c_long != 0 && (*hints).window_group != 0 {
+            Some((*hints).window_group)
+        } else {
+            None
+        };
+        XFree(hints as *mut c_void);
+        group
+    }
+}
 
-        glEnd();
+// the space a window reserves along the edges of the screen, in _NET_WM_STRUT_PARTIAL's order: left, right, top, bottom,
+// then where along each edge that starts and ends. plain _NET_WM_STRUT reserves the whole length of each edge
+pub fn get_strut(display: *mut Display, window: Window) -> Option<[u32; 12]> {
+    if let Some(values) = get_cardinal_property(display, window, "_NET_WM_STRUT_PARTIAL").filter(|values| values.len() >= 12) {
+        let mut strut = [0; 12];
+        for (reserved, value) in strut.iter_mut().zip(values) {
+            *reserved = value as u32;
+        }
+        return Some(strut);
+    }
+    let values = get_cardinal_property(display, window, "_NET_WM_STRUT").filter(|values| values.len() >= 4)?;
+    let [left, right, top, bottom] = [values[0] as u32, values[1] as u32, values[2] as u32, values[3] as u32];
+    Some([left, right, top, bottom, 0, u32::MAX, 0, u32::MAX, 0, u32::MAX, 0, u32::MAX])
+}
 
-        glDeleteTextures(1, &texture);
-        if draw_frame {
-            glDeleteTextures(1, &frame_texture);
+// the flags and window gravity from WM_NORMAL_HINTS; gravity is north west (the default) if the client didn't set one
+pub fn get_normal_hints(display: *mut Display, window: Window) -> (c_long, c_int) {
+    unsafe {
+        let mut hints: XSizeHints = mem::zeroed();
+        let mut supplied: c_long = 0;
+        if XGetWMNormalHints(display, window, &mut hints, &mut supplied) == 0 {
+            return (0, NorthWestGravity as c_int);
         }
-        XDestroyImage(xim);
-        if draw_frame {
-            XDestroyImage(frame_xim);
+        let gravity = if hints.flags & PWinGravity as c_long != 0 { hints.win_gravity } else { NorthWestGravity as c_int };
+        (hints.flags, gravity)
+    }
+}
+
+// every monitor that's on, from xrandr's crtcs; mirrored outputs share a crtc (or at least a rectangle) so they only count once.
+// empty if xrandr isn't there or has nothing lit up
+pub fn get_monitors(display: *mut Display, root: Window) -> Vec<Rect> {
+    let mut monitors: Vec<Rect> = Vec::new();
+    unsafe {
+        let resources = XRRGetScreenResourcesCurrent(display, root);
+        if resources.is_null() {
+            return monitors;
         }
+        for i in 0..(*resources).ncrtc as usize {
+            let crtc = XRRGetCrtcInfo(display, resources, *(*resources).crtcs.add(i));
+            if crtc.is_null() {
+                continue;
+            }
+            // a crtc without a mode or any outputs is switched off
+            if (*crtc).mode != 0 && (*crtc).noutput > 0 {
+                let rect = Rect { x: (*crtc).x, y: (*crtc).y, width: (*crtc).width as i32, height: (*crtc).height as i32 };
+                let output = XRRGetOutputInfo(display, resources, *(*crtc).outputs);
+                let name = if output.is_null() { String::from("?") } else { CStr::from_ptr((*output).name).to_string_lossy().into_owned() };
+                if !output.is_null() {
+                    XRRFreeOutputInfo(output);
+                }
+                println!("monitor {}: {}x{}+{}+{}", name, rect.width, rect.height, rect.x, rect.y);
+                if !monitors.contains(&rect) {
+                    monitors.push(rect);
+                }
+            }
+            XRRFreeCrtcInfo(crtc);
+        }
+        XRRFreeScreenResources(resources);
     }
-}
\ No newline at end of file
+    monitors
+}
+
+// how often each monitor that's on refreshes, in hz, worked out from its mode's timings
+pub fn get_refresh_rates(display: *mut Display, root: Window) -> Vec<f64> {
+    let mut rates: Vec<f64> = Vec::new();
+    unsafe {
+        let resources = XRRGetScreenResourcesCurrent(display, root);
+        if resources.is_null() {
+            return rates;
+        }
+        let modes = std::slice::from_raw_parts((*resources).modes, (*resources).nmode as usize);
+        for i in 0..(*resources).ncrtc as usize {
+            let crtc = XRRGetCrtcInfo(display, resources, *(*resources).crtcs.add(i));
+            if crtc.is_null() {
+                continue;
+            }
+            if let Some(mode) = modes.iter().find(|mode| mode.id == (*crtc).mode && mode.hTotal > 0 && mode.vTotal > 0) {
+                let mut rate = mode.dotClock as f64 / (mode.hTotal as f64 * mode.vTotal as f64);
+                // interlaced modes show half a picture per pass, doublescanned ones every line twice
+                if mode.modeFlags & RR_Interlace as c_ulong != 0 {
+                    rate *= 2.0;
+                }
+                if mode.modeFlags & RR_DoubleScan as c_ulong != 0 {
+                    rate /= 2.0;
+                }
+                println!("crtc {} refreshes at {:.2}hz", i, rate);
+                rates.push(rate);
+            }
+            XRRFreeCrtcInfo(crtc);
+        }
+        XRRFreeScreenResources(resources);
+    }
+    rates
+}
+
+// where the pointer is on the screen
+pub fn get_pointer_position(display: *mut Display, root: Window) -> (i32, i32) {
+    let (mut root_return, mut child_return): (Window, Window) = (0, 0);
+    let (mut x, mut y, mut win_x, mut win_y): (c_int, c_int, c_int, c_int) = (0, 0, 0, 0);
+    let mut mask: c_uint = 0;
+    unsafe {
+        XQueryPointer(display, root, &mut root_return, &mut child_return, &mut x, &mut y, &mut win_x, &mut win_y, &mut mask);
+    }
+    (x, y)
+}
@@ -0,0 +1,278 @@
+// tiling: works out where the tiled windows on a workspace go. every rectangle here is a frame, not a client
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    // cuts off the left part, ratio of the width; returns (left, right)
+    fn split_x(&self, ratio: f32) -> (Rect, Rect) {
+        let left = (self.width as f32 * ratio).round() as i32;
+        (Rect { width: left, ..*self }, Rect { x: self.x + left, width: self.width - left, ..*self })
+    }
+
+    // cuts off the top part, ratio of the height; returns (top, bottom)
+    fn split_y(&self, ratio: f32) -> (Rect, Rect) {
+        let top = (self.height as f32 * ratio).round() as i32;
+        (Rect { height: top, ..*self }, Rect { y: self.y + top, height: self.height - top, ..*self })
+    }
+}
+
+// n equal columns side by side, the last one gets whatever rounding left over
+fn columns(area: Rect, n: usize) -> Vec<Rect> {
+    let mut rects = Vec::with_capacity(n);
+    let mut remaining = area;
+    for i in 0..n {
+        let (column, rest) = remaining.split_x(1.0 / (n - i) as f32);
+        rects.push(column);
+        remaining = rest;
+    }
+    rects
+}
+
+// n equal rows on top of each other
+fn rows(area: Rect, n: usize) -> Vec<Rect> {
+    let mut rects = Vec::with_capacity(n);
+    let mut remaining = area;
+    for i in 0..n {
+        let (row, rest) = remaining.split_y(1.0 / (n - i) as f32);
+        rects.push(row);
+        remaining = rest;
+    }
+    rects
+}
+
+#[derive(Clone, Copy)]
+pub struct LayoutSettings {
+    pub gap: i32, // between tiled windows
+    pub outer_gap: i32, // between tiled windows and the edge of the screen
+    pub master_ratio: f32, // how much of the width the master column gets
+    pub master_count: usize, // how many windows go in the master column
+}
+
+impl Default for LayoutSettings {
+    fn default() -> Self {
+        LayoutSettings {
+            gap: 10,
+            outer_gap: 10,
+            master_ratio: 0.55,
+            master_count: 1,
+        }
+    }
+}
+
+pub trait Layout {
+    fn name(&self) -> &'static str;
+
+    // false for layouts that leave windows wherever they were put
+    fn tiles(&self) -> bool {
+        true
+    }
+
+    // where each of count windows goes inside area, oldest window first; gaps are dealt with by arrange
+    fn tile(&self, area: Rect, count: usize, settings: &LayoutSettings) -> Vec<Rect>;
+}
+
+// no tiling at all, the way cwm has always been
+pub struct Floating;
+
+impl Layout for Floating {
+    fn name(&self) -> &'static str {
+        "floating"
+    }
+
+    fn tiles(&self) -> bool {
+        false
+    }
+
+    fn tile(&self, _area: Rect, _count: usize, _settings: &LayoutSettings) -> Vec<Rect> {
+        Vec::new()
+    }
+}
+
+// the oldest windows in a column on the left, everything else stacked up on the right
+pub struct MasterStack;
+
+impl Layout for MasterStack {
+    fn name(&self) -> &'static str {
+        "master-stack"
+    }
+
+    fn tile(&self, area: Rect, count: usize, settings: &LayoutSettings) -> Vec<Rect> {
+        let masters = settings.master_count.min(count);
+        // with only one kind of window there's nothing to split
+        if masters == 0 || masters == count {
+            return rows(area, count);
+        }
+        let (master_area, stack_area) = area.split_x(settings.master_ratio);
+        let mut rects = rows(master_area, masters);
+        rects.extend(rows(stack_area, count - masters));
+        rects
+    }
+}
+
+// every window gets an equally wide column
+pub struct Columns;
+
+impl Layout for Columns {
+    fn name(&self) -> &'static str {
+        "columns"
+    }
+
+    fn tile(&self, area: Rect, count: usize, _settings: &LayoutSettings) -> Vec<Rect> {
+        columns(area, count)
+    }
+}
+
+// every window takes up the whole screen, on top of each other
+pub struct Monocle;
+
+impl Layout for Monocle {
+    fn name(&self) -> &'static str {
+        "monocle"
+    }
+
+    fn tile(&self, area: Rect, count: usize, _settings: &LayoutSettings) -> Vec<Rect> {
+        vec![area; count]
+    }
+}
+
+// each window halves whatever space is left along its longer side, so the windows spiral inwards
+pub struct Bsp;
+
+impl Layout for Bsp {
+    fn name(&self) -> &'static str {
+        "bsp"
+    }
+
+    fn tile(&self, area: Rect, count: usize, _settings: &LayoutSettings) -> Vec<Rect> {
+        let mut rects = Vec::with_capacity(count);
+        let mut remaining = area;
+        for i in 0..count {
+            if i == count - 1 {
+                rects.push(remaining);
+                break;
+            }
+            let (taken, rest) = if remaining.width >= remaining.height { remaining.split_x(0.5) } else { remaining.split_y(0.5) };
+            rects.push(taken);
+            remaining = rest;
+        }
+        rects
+    }
+}
+
+// every layout there is; config and client messages refer to them by their index in here
+pub fn builtin() -> Vec<Box<dyn Layout>> {
+    vec![Box::new(Floating), Box::new(MasterStack), Box::new(Columns), Box::new(Monocle), Box::new(Bsp)]
+}
+
+pub fn index_of(name: &str) -> Option<usize> {
+    builtin().iter().position(|layout| layout.name() == name)
+}
+
+// lays count windows out in area with the gaps from settings around and between them
+pub fn arrange(layout: &dyn Layout, area: Rect, count: usize, settings: &LayoutSettings) -> Vec<Rect> {
+    // every tile gives up a whole gap, half on each side (the odd pixel of an odd gap on the right and bottom),
+    // so the outside only needs the rest of the outer gap taken off
+    let half_gap = settings.gap / 2;
+    let area = Rect {
+        x: area.x + settings.outer_gap - half_gap,
+        y: area.y + settings.outer_gap - half_gap,
+        width: (area.width - settings.outer_gap * 2 + settings.gap).max(1),
+        height: (area.height - settings.outer_gap * 2 + settings.gap).max(1),
+    };
+    layout.tile(area, count, settings).into_iter().map(|rect| Rect {
+        x: rect.x + half_gap,
+        y: rect.y + half_gap,
+        width: (rect.width - settings.gap).max(1),
+        height: (rect.height - settings.gap).max(1),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_gaps() -> LayoutSettings {
+        LayoutSettings { gap: 0, outer_gap: 0, ..LayoutSettings::default() }
+    }
+
+    #[test]
+    fn master_stack_splits_master_column_from_stack() {
+        let rects = MasterStack.tile(Rect::new(0, 0, 1000, 600), 3, &no_gaps());
+        assert_eq!(rects, vec![Rect::new(0, 0, 550, 600), Rect::new(550, 0, 450, 300), Rect::new(550, 300, 450, 300)]);
+    }
+
+    #[test]
+    fn master_stack_uses_rows_when_everything_is_a_master() {
+        let settings = LayoutSettings { master_count: 3, ..no_gaps() };
+        let rects = MasterStack.tile(Rect::new(0, 0, 1000, 600), 2, &settings);
+        assert_eq!(rects, vec![Rect::new(0, 0, 1000, 300), Rect::new(0, 300, 1000, 300)]);
+        assert_eq!(MasterStack.tile(Rect::new(0, 0, 1000, 600), 1, &no_gaps()), vec![Rect::new(0, 0, 1000, 600)]);
+        assert!(MasterStack.tile(Rect::new(0, 0, 1000, 600), 0, &no_gaps()).is_empty());
+    }
+
+    #[test]
+    fn bsp_halves_the_longer_side_each_time() {
+        let rects = Bsp.tile(Rect::new(0, 0, 1000, 600), 4, &no_gaps());
+        assert_eq!(rects, vec![Rect::new(0, 0, 500, 600), Rect::new(500, 0, 500, 300), Rect::new(500, 300, 250, 300), Rect::new(750, 300, 250, 300)]);
+        assert_eq!(Bsp.tile(Rect::new(0, 0, 1000, 600), 1, &no_gaps()), vec![Rect::new(0, 0, 1000, 600)]);
+        assert!(Bsp.tile(Rect::new(0, 0, 1000, 600), 0, &no_gaps()).is_empty());
+    }
+
+    #[test]
+    fn rounding_never_loses_a_pixel() {
+        for count in 1..8 {
+            let area = Rect::new(7, 3, 1001, 599);
+            let tiles = columns(area, count);
+            assert_eq!(tiles.iter().map(|tile| tile.width).sum::<i32>(), area.width);
+            assert_eq!(tiles.last().map(|tile| tile.x + tile.width), Some(area.x + area.width));
+            let tiles = rows(area, count);
+            assert_eq!(tiles.iter().map(|tile| tile.height).sum::<i32>(), area.height);
+            assert_eq!(tiles.last().map(|tile| tile.y + tile.height), Some(area.y + area.height));
+            // bsp tiles cover the area exactly, without overlapping
+            let tiles = Bsp.tile(area, count, &no_gaps());
+            assert_eq!(tiles.iter().map(|tile| tile.width * tile.height).sum::<i32>(), area.width * area.height);
+        }
+    }
+
+    #[test]
+    fn arrange_puts_gaps_between_tiles_and_outer_gaps_around_them() {
+        let settings = LayoutSettings { gap: 10, outer_gap: 20, master_ratio: 0.5, master_count: 1 };
+        let rects = arrange(&MasterStack, Rect::new(0, 0, 1000, 600), 3, &settings);
+        assert_eq!(rects, vec![Rect::new(20, 20, 475, 560), Rect::new(505, 20, 475, 275), Rect::new(505, 305, 475, 275)]);
+    }
+
+    #[test]
+    fn arrange_keeps_odd_gaps_exact() {
+        let settings = LayoutSettings { gap: 7, outer_gap: 9, master_ratio: 0.5, master_count: 1 };
+        let area = Rect::new(100, 50, 1000, 600);
+        let rects = arrange(&Columns, area, 3, &settings);
+        assert_eq!(rects[0].x - area.x, 9);
+        assert_eq!(rects[1].x - (rects[0].x + rects[0].width), 7);
+        assert_eq!(rects[2].x - (rects[1].x + rects[1].width), 7);
+        assert_eq!(area.x + area.width - (rects[2].x + rects[2].width), 9);
+        for tile in &rects {
+            assert_eq!(tile.y - area.y, 9);
+            assert_eq!(area.y + area.height - (tile.y + tile.height), 9);
+        }
+    }
+
+    #[test]
+    fn monocle_gives_every_window_the_whole_area() {
+        let rects = arrange(&Monocle, Rect::new(0, 0, 1000, 600), 2, &LayoutSettings::default());
+        assert_eq!(rects, vec![Rect::new(10, 10, 980, 580); 2]);
+    }
+}
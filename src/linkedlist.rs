@@ -179,6 +179,27 @@ impl LinkedList {
         }
     }
 
+    // stable sorts the windows by key, lowest at the bottom. only the links change, so no window gets moved in memory
+    pub fn sort_by_key<K: Ord>(&mut self, key: impl Fn(&types::CumWindow) -> K) {
+        let mut elements: Vec<*mut Element> = Vec::with_capacity(self.length);
+        let mut current = self.bottom;
+        while let Some(element) = current {
+            elements.push(element);
+            current = unsafe { (*element).next };
+        }
+        elements.sort_by_key(|element| key(unsafe { &(**element).value }));
+        unsafe {
+            for pair in elements.windows(2) {
+                (*pair[0]).next = Some(pair[1]);
+            }
+            if let Some(&last) = elements.last() {
+                (*last).next = None;
+            }
+        }
+        self.bottom = elements.first().copied();
+        self.top = elements.last().copied();
+    }
+
     pub fn len(&self) -> usize {
         self.length
     }
@@ -2,40 +2,62 @@ mod types;
 mod helpers;
 mod linkedlist;
 mod setup;
+mod config;
+mod physics;
+mod animation;
+mod shadow;
+mod blur;
+mod renderer;
+mod shaders;
+mod theme;
+mod wallpaper;
+mod background;
+mod transition;
+mod layout;
+mod remote;
+mod snap;
+mod frame_clock;
 
 use std::borrow::Borrow;
-use std::ffi::{c_void, CStr};
+use std::collections::HashMap;
+use std::ffi::CStr;
 use std::mem;
-use std::os::raw::{c_char, c_int, c_uint, c_ulong};
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong};
 use std::ptr::{null, null_mut};
-use std::time::SystemTime;
+use std::time::Duration;
 use fast_image_resize as fr;
-use libsex::bindings::{AnyModifier, Button1Mask, ButtonPressMask, ButtonReleaseMask,
-                       CopyFromParent, CWBackPixel, CWX, CWY,
-                       Display, GL_ARRAY_BUFFER, GL_BLEND, GL_COLOR_BUFFER_BIT, GL_DEPTH_BUFFER_BIT,
-                       GL_FALSE, GL_FLOAT, GL_FRAGMENT_SHADER, GL_ONE_MINUS_SRC_ALPHA,
-                       GL_PROJECTION, GL_SRC_ALPHA, GL_STATIC_DRAW, GL_VERTEX_SHADER,
-                       glAttachShader, glBindBuffer, glBindVertexArray, glBlendFunc, GLboolean,
-                       glBufferData, GLclampf, glClear, glClearColor, glCompileShader,
-                       glCreateProgram, glCreateShader, glEnable,
-                       glEnableVertexArrayAttrib, glGenBuffers, glGenVertexArrays,
-                       glGetAttribLocation, glLinkProgram, glLoadIdentity,
-                       glMatrixMode, glShaderSource, GLsizeiptr, GLuint,
-                       gluOrtho2D, glUseProgram, glVertexArrayAttribBinding,
-                       glVertexArrayAttribFormat, glViewport, glXSwapBuffers, GrabModeAsync,
-                       InputOutput, PictTypeDirect, PointerMotionMask,
-                       QueuedAlready, Screen, Visual, Window, XConfigureWindow, XCreateWindow,
-                       XDefaultScreenOfDisplay, XEvent, XEventsQueued, XFlush, XGetErrorText,
-                       XGetWindowAttributes, XGrabButton, XMapWindow, XMoveWindow,
-                       XNextEvent, XOpenDisplay, XQueryPointer, XRaiseWindow, XRenderFindVisualFormat,
-                       XResizeWindow, XRootWindowOfScreen, XSendEvent, XSetErrorHandler,
-                       XSetWindowAttributes, XSync, XWindowAttributes, XWindowChanges};
-
-
-use crate::types::{CumWindow, XVelocity};
-use crate::helpers::{allow_input_passthrough, draw_x_window, get_window_fb_config, redraw_desktop, rgba_to_bgra};
+use libsex::bindings::{QueuedAfterReading, QueuedAlready, XScreenNumberOfScreen, RRScreenChangeNotify, XRRUpdateConfiguration,
+                       CenterGravity, EastGravity, NorthEastGravity, NorthGravity, NorthWestGravity, PPosition,
+                       SouthEastGravity, SouthGravity, SouthWestGravity, USPosition, WestGravity, Above, AnyModifier,
+                       Atom, Button1Mask, ButtonPressMask, ButtonReleaseMask, CopyFromParent, CWBackPixel, CWSibling,
+                       CWStackMode, CWX, CWY, Display, GL_COLOR_BUFFER_BIT, GL_DEPTH_BUFFER_BIT, IconicState, NormalState,
+                       CurrentTime, PropertyChangeMask, RevertToPointerRoot, GLclampf, glClear, glClearColor,
+                       GrabModeAsync, InputOutput, IsViewable, PictTypeDirect, PointerMotionMask, Screen, Visual,
+                       Window, XConfigureWindow, XCreateWindow, XDefaultScreenOfDisplay, XDestroyWindow, XEvent,
+                       XEventsQueued, XFlush, XGetErrorText, XGetWindowAttributes, XGrabButton, XLowerWindow,
+                       XMapWindow, XMoveResizeWindow, XMoveWindow, XNextEvent, XOpenDisplay, XQueryPointer,
+                       XRaiseWindow, XRenderFindVisualFormat, XResizeWindow, XRootWindowOfScreen, XSendEvent,
+                       XSetErrorHandler, XSelectInput, XSetInputFocus, XSetWindowAttributes, XSync, XUnmapWindow,
+                       XWindowAttributes, XWindowChanges};
+
+
+use crate::types::{ClosingWindow, CumWindow, WindowState, WindowType, XVelocity};
+use crate::config::{Config, RuleSettings};
+use crate::physics::WobblyMesh;
+use crate::helpers::{allow_input_passthrough, cache_atoms, draw_window_textures, draw_x_window, get_atom, get_atom_name, get_blur_region, get_cardinal_property, get_monitors, get_pointer_position, get_refresh_rates, get_strut, get_transient_for, get_window_class, get_window_group, get_window_fb_config, get_window_opacity, get_window_type, redraw_desktop, rgba_to_bgra, set_cardinal_property, set_utf8_list_property, workspace_from_cardinal, workspace_to_cardinal, WindowTextures};
+use crate::animation::{Animation, AnimationKind, Transform};
+use crate::renderer::Renderer;
+use crate::shaders::CustomShaders;
+use crate::theme::Theme;
+use crate::shadow::{Shadow, ShadowCache};
+use crate::blur::Blur;
 use crate::linkedlist::LinkedList;
-use crate::setup::{setup_compositing, setup_desktop, setup_glx};
+use crate::setup::{put_wallpaper, resize_desktop, set_redirected, ALLOWED_ACTIONS, setup_compositing, setup_desktop, setup_ewmh, setup_glx,
+                   setup_randr};
+use crate::background::Background;
+use crate::transition::{Layer, WorkspaceTransitions};
+use crate::layout::{Layout, LayoutSettings, Rect};
+use crate::frame_clock::FrameClock;
 
 unsafe extern "C" fn error_handler(display: *mut Display, error_event: *mut libsex::bindings::XErrorEvent) -> c_int {
     let mut buffer: [c_char; 256] = [0; 256];
@@ -44,6 +66,521 @@ unsafe extern "C" fn error_handler(display: *mut Display, error_event: *mut libs
     0
 }
 
+// where a window shrinks to when minimized: its taskbar button if the taskbar told us, otherwise the bottom of the screen
+fn minimize_target(display: *mut Display, window: Window, src_width: c_int, src_height: c_int) -> (f32, f32, f32, f32) {
+    match get_cardinal_property(display, window, "_NET_WM_ICON_GEOMETRY") {
+        Some(geometry) if geometry.len() >= 4 => (geometry[0] as f32, geometry[1] as f32, geometry[2] as f32, geometry[3] as f32),
+        _ => ((src_width / 2) as f32, src_height as f32, 1.0, 1.0),
+    }
+}
+
+// the drop shadow for a window, or none if the config or a rule says it shouldn't have one
+fn window_shadow(w: &CumWindow, config: &Config, shadow_cache: &mut ShadowCache) -> Option<Shadow> {
+    let wanted = w.rule.shadow.unwrap_or(config.shadows && !config.shadow_exclude.contains(&w.window_type));
+    // shadows are cut out for the frame, so windows without one (fullscreen ones included) don't get any
+    if !wanted || !shows_frame(w) {
+        return None;
+    }
+    Some(Shadow {
+        texture: shadow_cache.get(w.width + 20, w.frame_height(), window_corner_radius(w, config) as u16),
+        radius: config.shadow_radius as f32,
+        offset_x: config.shadow_offset_x,
+        offset_y: config.shadow_offset_y,
+        color: config.shadow_color,
+        opacity: config.shadow_opacity,
+    })
+}
+
+// how round a window's corners are; a fullscreen window fills its monitor and has no corners to round
+fn window_corner_radius(w: &CumWindow, config: &Config) -> f32 {
+    if !shows_frame(w) || !w.rule.rounded_corners.unwrap_or(true) {
+        return 0.0;
+    }
+    config.corner_radius
+}
+
+// which custom shader a window gets, if any: rules win over the global settings, and the inactive shader
+// is only used for windows without focus
+fn window_shader(w: &CumWindow, config: &Config, focused: bool) -> Option<usize> {
+    let shader = w.rule.shader.or(config.window_shader);
+    if focused {
+        return shader;
+    }
+    w.rule.inactive_shader.or(config.inactive_shader).or(shader)
+}
+
+// sticky windows are on every workspace
+fn on_workspace(w: &CumWindow, workspace: usize) -> bool {
+    w.workspace.map_or(true, |window_workspace| window_workspace == workspace)
+}
+
+// whether a tiling layout gets to decide where a window goes
+fn is_tiled(w: &CumWindow) -> bool {
+    let tileable = matches!(w.window_type, WindowType::Normal | WindowType::Dialog | WindowType::Utility);
+    !w.floating && !w.hide && tileable && w.workspace.is_some()
+        && !w.state.fullscreen && !w.state.maximized() && !w.state.shaded
+}
+
+// whether a window's frame should be up; fullscreen windows and ones that aren't decorated don't get one
+fn shows_frame(w: &CumWindow) -> bool {
+    w.decorated && !w.state.fullscreen
+}
+
+// atoms the event loop compares against, interned once at startup
+const WATCHED_ATOMS: [&str; 16] = [
+    "_NET_WM_WINDOW_OPACITY",
+    "_KDE_NET_WM_BLUR_BEHIND_REGION",
+    "_NET_WM_STRUT_PARTIAL",
+    "_NET_WM_STRUT",
+    "_NET_WM_BYPASS_COMPOSITOR",
+    "WM_TRANSIENT_FOR",
+    "_NET_WM_WINDOW_TYPE",
+    "WM_CHANGE_STATE",
+    "WM_STATE",
+    "_NET_CURRENT_DESKTOP",
+    "_NET_WM_DESKTOP",
+    "_CWM_LAYOUT",
+    "_CWM_TOGGLE_FLOATING",
+    "_NET_WM_STATE",
+    "_NET_REQUEST_FRAME_EXTENTS",
+    "_NET_FRAME_EXTENTS",
+];
+
+// how far the frame sticks out past the client: left, right, top (the title bar) and bottom, as _NET_FRAME_EXTENTS has them
+const FRAME_EXTENTS: [u64; 4] = [10, 10, 20, 5];
+
+fn frame_extents(w: &CumWindow) -> [u64; 4] {
+    if shows_frame(w) { FRAME_EXTENTS } else { [0; 4] }
+}
+
+// the extents a window that isn't mapped yet is going to get, going by its type
+fn expected_frame_extents(window_type: WindowType) -> [u64; 4] {
+    if window_type.decorated() { FRAME_EXTENTS } else { [0; 4] }
+}
+
+// where a client has to go so that the point of its frame picked by its window gravity ends up at (x, y),
+// e.g. north west puts the frame's corner there, south the middle of its bottom edge, static the client itself
+fn apply_gravity(gravity: c_int, extents: [u64; 4], x: i32, y: i32) -> (i32, i32) {
+    let [left, right, top, bottom] = extents.map(|extent| extent as i32);
+    let gravity = gravity as u32;
+    let dx = match gravity {
+        NorthWestGravity | WestGravity | SouthWestGravity => left,
+        NorthGravity | CenterGravity | SouthGravity => (left - right) / 2,
+        NorthEastGravity | EastGravity | SouthEastGravity => -right,
+        _ => 0,
+    };
+    let dy = match gravity {
+        NorthWestGravity | NorthGravity | NorthEastGravity => top,
+        WestGravity | CenterGravity | EastGravity => (top - bottom) / 2,
+        SouthWestGravity | SouthGravity | SouthEastGravity => -bottom,
+        _ => 0,
+    };
+    (x + dx, y + dy)
+}
+
+// WM_TRANSIENT_FOR; transient for root means it belongs to the whole group, which we take to mean the group's leader
+fn transient_parent(display: *mut Display, root: Window, window: Window) -> Option<Window> {
+    match get_transient_for(display, window) {
+        Some(parent) if parent == root => get_window_group(display, window).filter(|&leader| leader != window),
+        parent => parent,
+    }
+}
+
+// looks a managed window up by its client window id
+fn find_window(windows: &LinkedList, window: Window) -> Option<CumWindow> {
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        let w = unsafe { (*element).value };
+        if w.window_id == window {
+            return Some(w);
+        }
+        el = windows.next_element(element);
+    }
+    None
+}
+
+// the part of a monitor that isn't taken up by panels, which is where windows get placed, maximized, snapped and tiled.
+// struts are bands along the edges of the whole screen, so they only take space from a monitor they reach into.
+// docks that don't set a strut keep whichever edge of the monitor they're sitting on
+fn work_area(windows: &LinkedList, monitor: Rect, screen: Rect) -> Rect {
+    let (mut left, mut top, mut right, mut bottom) = (monitor.x, monitor.y, monitor.x + monitor.width, monitor.y + monitor.height);
+    let (screen_right, screen_bottom) = (screen.x + screen.width, screen.y + screen.height);
+    // strut ranges include their end
+    let overlaps = |start: u32, end: u32, from: i32, to: i32| (start as i64) < to as i64 && (end as i64) >= from as i64;
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        let w = unsafe { (*element).value };
+        el = windows.next_element(element);
+        if w.hide {
+            continue;
+        }
+        if let Some(strut) = w.strut {
+            let [strut_left, strut_right, strut_top, strut_bottom] = [strut[0] as i32, strut[1] as i32, strut[2] as i32, strut[3] as i32];
+            if strut_left > 0 && overlaps(strut[4], strut[5], monitor.y, monitor.y + monitor.height) {
+                left = left.max(screen.x + strut_left);
+            }
+            if strut_right > 0 && overlaps(strut[6], strut[7], monitor.y, monitor.y + monitor.height) {
+                right = right.min(screen_right - strut_right);
+            }
+            if strut_top > 0 && overlaps(strut[8], strut[9], monitor.x, monitor.x + monitor.width) {
+                top = top.max(screen.y + strut_top);
+            }
+            if strut_bottom > 0 && overlaps(strut[10], strut[11], monitor.x, monitor.x + monitor.width) {
+                bottom = bottom.min(screen_bottom - strut_bottom);
+            }
+            continue;
+        }
+        if w.window_type != WindowType::Dock {
+            continue;
+        }
+        let (dock_right, dock_bottom) = (w.x + w.width as i32, w.y + w.height as i32);
+        // off this monitor altogether
+        if w.x >= monitor.x + monitor.width || dock_right <= monitor.x || w.y >= monitor.y + monitor.height || dock_bottom <= monitor.y {
+            continue;
+        }
+        // wider than it's tall means it's a bar along the top or bottom, otherwise along a side
+        if w.width >= w.height {
+            if w.y <= monitor.y {
+                top = top.max(dock_bottom);
+            } else if dock_bottom >= monitor.y + monitor.height {
+                bottom = bottom.min(w.y);
+            }
+        } else if w.x <= monitor.x {
+            left = left.max(dock_right);
+        } else if dock_right >= monitor.x + monitor.width {
+            right = right.min(w.x);
+        }
+    }
+    Rect { x: left, y: top, width: (right - left).max(1), height: (bottom - top).max(1) }
+}
+
+// writes _NET_CLIENT_LIST, so taskbars know which windows there are
+fn publish_client_list(display: *mut Display, root: Window, client_list: &[Window]) {
+    let windows: Vec<u64> = client_list.iter().map(|&window| window as u64).collect();
+    set_cardinal_property(display, root, "_NET_CLIENT_LIST", "WINDOW", &windows);
+}
+
+// writes _NET_WM_STATE for taskbars and pagers, sticky and hidden included
+fn publish_state(display: *mut Display, w: &CumWindow) {
+    let mut names = w.state.names();
+    if w.workspace.is_none() {
+        names.push("_NET_WM_STATE_STICKY");
+    }
+    if w.minimized {
+        names.push("_NET_WM_STATE_HIDDEN");
+    }
+    let atoms: Vec<u64> = names.iter().map(|name| get_atom(display, name) as u64).collect();
+    set_cardinal_property(display, w.window_id, "_NET_WM_STATE", "ATOM", &atoms);
+    // going fullscreen takes the frame away, so the extents change along with the state
+    set_cardinal_property(display, w.window_id, "_NET_FRAME_EXTENTS", "CARDINAL", &frame_extents(w));
+}
+
+// writes the icccm WM_STATE, which is how clients find out they've been iconified (NormalState or IconicState)
+fn publish_wm_state(display: *mut Display, window: Window, state: u32) {
+    // the second value is the icon window, we never make one
+    set_cardinal_property(display, window, "WM_STATE", "WM_STATE", &[state as u64, 0]);
+}
+
+// the monitors xrandr knows about, or the whole screen as a single monitor if there aren't any (or no xrandr at all)
+fn find_monitors(display: *mut Display, root: Window, randr: bool, src_width: c_int, src_height: c_int) -> Vec<Rect> {
+    let monitors = if randr { get_monitors(display, root) } else { Vec::new() };
+    if monitors.is_empty() {
+        vec![Rect::new(0, 0, src_width, src_height)]
+    } else {
+        monitors
+    }
+}
+
+// the monitor (and its work area) that (x, y) is on; anywhere off every monitor counts as being on the first one
+fn monitor_at(monitor_areas: &[(Rect, Rect)], x: i32, y: i32) -> (Rect, Rect) {
+    monitor_areas.iter().copied().find(|(monitor, _)| monitor.contains(x, y)).unwrap_or(monitor_areas[0])
+}
+
+// every monitor along with its work area
+fn monitor_work_areas(windows: &LinkedList, monitors: &[Rect], screen: Rect) -> Vec<(Rect, Rect)> {
+    monitors.iter().map(|&monitor| (monitor, work_area(windows, monitor, screen))).collect()
+}
+
+// where a window's client goes while it's fullscreen (over its monitor) or maximized (over the monitor's work area),
+// or None if it's neither. saved is where it was before, which a direction that isn't maximized keeps
+fn state_geometry(w: &CumWindow, saved: (i32, i32, u16, u16), screen: Rect, area: Rect) -> Option<(i32, i32, u16, u16)> {
+    if w.state.fullscreen {
+        return Some((screen.x, screen.y, screen.width as u16, screen.height as u16));
+    }
+    if !w.state.maximized() {
+        return None;
+    }
+    // maximizing is for the frame, the client sits inside it
+    let (mut x, mut y, mut width, mut height) = saved;
+    if w.state.maximized_horz {
+        x = area.x + 10;
+        width = (area.width - 20).max(1) as u16;
+    }
+    if w.state.maximized_vert {
+        y = area.y + 20;
+        height = (area.height - 25).max(1) as u16;
+    }
+    Some((x, y, width, height))
+}
+
+// moves and resizes a window to match its fullscreen, maximized and shaded states, remembering where it was
+// before so it goes back there once it's none of them. fullscreen windows don't get a frame at all
+fn apply_state(display: *mut Display, w: &mut CumWindow, saved_geometries: &mut HashMap<Window, (i32, i32, u16, u16)>, monitor_areas: &[(Rect, Rect)]) {
+    let saved = *saved_geometries.entry(w.window_id).or_insert((w.x, w.y, w.width, w.height));
+    // whichever monitor it was on before, so it stays there
+    let (screen, area) = monitor_at(monitor_areas, saved.0 + saved.2 as i32 / 2, saved.1 + saved.3 as i32 / 2);
+    let (x, y, width, height) = match state_geometry(w, saved, screen, area) {
+        Some(geometry) => geometry,
+        None => {
+            saved_geometries.remove(&w.window_id);
+            saved
+        }
+    };
+    w.x = x;
+    w.y = y;
+    w.width = width;
+    w.height = height;
+    unsafe {
+        XMoveResizeWindow(display, w.window_id, x, y, width as c_uint, height as c_uint);
+        XMoveResizeWindow(display, w.frame_id, x - 10, y - 20, (width + 20) as c_uint, w.frame_height() as c_uint);
+        if !shows_frame(w) {
+            XUnmapWindow(display, w.frame_id);
+        } else if !w.hide {
+            XMapWindow(display, w.frame_id);
+        }
+    }
+}
+
+// which layer a window is drawn and stacked in: desktop windows at the very bottom, then below windows,
+// then everything else, with panels and above windows over it and fullscreen windows over those
+fn stacking_layer(w: &CumWindow) -> i32 {
+    if w.state.fullscreen {
+        2
+    } else if w.state.above || w.window_type == WindowType::Dock {
+        1
+    } else if w.window_type == WindowType::Desktop {
+        -2
+    } else if w.state.below {
+        -1
+    } else {
+        0
+    }
+}
+
+// indices of every window that belongs to window: its transients, their transients and so on
+fn transients_of(windows: &LinkedList, window: Window) -> Vec<usize> {
+    let mut all: Vec<CumWindow> = Vec::with_capacity(windows.len());
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        all.push(unsafe { (*element).value });
+        el = windows.next_element(element);
+    }
+    let mut family = vec![window];
+    let mut transients = Vec::new();
+    // keep going until a pass finds nobody new, however deep the dialogs go
+    loop {
+        let before = transients.len();
+        for (i, w) in all.iter().enumerate() {
+            let belongs = w.transient_for.map_or(false, |parent| family.contains(&parent));
+            if belongs && !family.contains(&w.window_id) {
+                family.push(w.window_id);
+                transients.push(i);
+            }
+        }
+        if transients.len() == before {
+            return transients;
+        }
+    }
+}
+
+// the same, for a queue of windows: adds everything that belongs to the windows already in it
+fn with_transients(windows: &LinkedList, queue: &mut Vec<Window>) {
+    for window in queue.clone() {
+        for i in transients_of(windows, window) {
+            let transient = unsafe { (*windows.index(i).unwrap()).value.window_id };
+            if !queue.contains(&transient) {
+                queue.push(transient);
+            }
+        }
+    }
+}
+
+// moves the windows with these ids by (dx, dy); x only gets told if tell_x is set, since dragged windows are
+// only really moved once they're dropped
+fn shift_windows(display: *mut Display, windows: &mut LinkedList, ids: &[Window], dx: i32, dy: i32, tell_x: bool) {
+    let mut el = windows.index(0);
+    let mut i = 0;
+    while let Some(element) = el {
+        let mut w = unsafe { (*element).value };
+        el = windows.next_element(element);
+        if ids.contains(&w.window_id) {
+            w.x += dx;
+            w.y += dy;
+            if tell_x {
+                unsafe {
+                    XMoveWindow(display, w.window_id, w.x, w.y);
+                }
+            }
+            windows.change_element_at_index(i, w).expect("Error changing window");
+        }
+        i += 1;
+    }
+}
+
+// puts windows back in their layers, in x and in the order we draw in, with transients right on top of the window they belong to
+// and popups we don't manage on top of everything
+fn restack(display: *mut Display, windows: &mut LinkedList, unmanaged_windows: &[CumWindow], desktop_id: Window) {
+    let mut stack: Vec<CumWindow> = Vec::with_capacity(windows.len());
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        stack.push(unsafe { (*element).value });
+        el = windows.next_element(element);
+    }
+    // a transient sorts as if it was the window it (eventually) belongs to, just after it
+    let positions: HashMap<Window, usize> = stack.iter().enumerate().map(|(i, w)| (w.window_id, i)).collect();
+    let by_id: HashMap<Window, CumWindow> = stack.iter().map(|w| (w.window_id, *w)).collect();
+    windows.sort_by_key(|w| {
+        let mut root = *w;
+        let mut depth = 0;
+        // (the depth limit is for clients that manage to make a loop out of WM_TRANSIENT_FOR)
+        while let Some(parent) = root.transient_for.and_then(|parent| by_id.get(&parent)) {
+            if depth > 16 {
+                break;
+            }
+            root = *parent;
+            depth += 1;
+        }
+        (stacking_layer(&root).max(stacking_layer(w)), positions[&root.window_id], depth, positions[&w.window_id])
+    });
+    stack.clear();
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        stack.push(unsafe { (*element).value });
+        el = windows.next_element(element);
+    }
+    unsafe {
+        // lowering puts a window under everything, so whatever goes lowest gets lowered last
+        for w in stack.iter().rev().filter(|w| stacking_layer(w) < 0) {
+            XLowerWindow(display, w.window_id);
+            XLowerWindow(display, w.frame_id);
+        }
+        for w in stack.iter().filter(|w| stacking_layer(w) > 0) {
+            XRaiseWindow(display, w.frame_id);
+            XRaiseWindow(display, w.window_id);
+        }
+        // parents come before their transients, so each one goes straight on top of a parent that's already in place
+        for w in stack.iter() {
+            let parent = match w.transient_for.and_then(|parent| by_id.get(&parent)) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let mut changes = XWindowChanges { x: 0, y: 0, width: 0, height: 0, border_width: 0, sibling: parent.window_id, stack_mode: Above as c_int };
+            XConfigureWindow(display, w.frame_id, CWSibling | CWStackMode, &mut changes);
+            changes.sibling = w.frame_id;
+            XConfigureWindow(display, w.window_id, CWSibling | CWStackMode, &mut changes);
+        }
+        for w in unmanaged_windows.iter().filter(|w| !w.hide) {
+            XRaiseWindow(display, w.window_id);
+        }
+        // the desktop always stays underneath
+        XLowerWindow(display, desktop_id);
+    }
+}
+
+// moves every tiled window on workspace to where the layout wants it. only x gets told straight away,
+// the wobbly meshes follow on their own so the windows spring over instead of jumping
+fn retile(display: *mut Display, windows: &mut LinkedList, layout: &dyn Layout, settings: &LayoutSettings,
+          monitor_areas: &[(Rect, Rect)], workspace: usize, holding_window: Window) {
+    if !layout.tiles() {
+        return;
+    }
+    // each monitor tiles the windows that are on it within its own work area
+    let mut tiled: Vec<(usize, Rect)> = Vec::new();
+    let mut el = windows.index(0);
+    let mut i = 0;
+    while let Some(element) = el {
+        let w = unsafe { (*element).value };
+        // the window being dragged around is the user's business
+        if is_tiled(&w) && w.workspace == Some(workspace) && w.window_id != holding_window {
+            let (monitor, _) = monitor_at(monitor_areas, w.x + w.width as i32 / 2, w.y + w.height as i32 / 2);
+            tiled.push((i, monitor));
+        }
+        el = windows.next_element(element);
+        i += 1;
+    }
+    let placements = monitor_areas.iter().flat_map(|&(monitor, area)| {
+        let on_monitor: Vec<usize> = tiled.iter().filter(|(_, on)| *on == monitor).map(|(i, _)| *i).collect();
+        let rects = layout::arrange(layout, area, on_monitor.len(), settings);
+        on_monitor.into_iter().zip(rects)
+    }).collect::<Vec<(usize, Rect)>>();
+    for (i, rect) in placements {
+        let mut w = unsafe { (*windows.index(i).unwrap()).value };
+        // rects are for the frame, the client sits inside it
+        let (x, y) = (rect.x + 10, rect.y + 20);
+        let (width, height) = ((rect.width - 20).max(1) as u16, (rect.height - 25).max(1) as u16);
+        if (w.x, w.y, w.width, w.height) == (x, y, width, height) {
+            continue;
+        }
+        unsafe {
+            XMoveResizeWindow(display, w.window_id, x, y, width as c_uint, height as c_uint);
+            XMoveResizeWindow(display, w.frame_id, x - 10, y - 20, (width + 20) as c_uint, (height + 25) as c_uint);
+        }
+        w.x = x;
+        w.y = y;
+        w.width = width;
+        w.height = height;
+        windows.change_element_at_index(i, w).expect("Error changing window");
+    }
+}
+
+// the window that could go straight to the screen without us: the topmost one on the workspace, if it's fullscreen
+// over its monitor and we wouldn't be drawing it any differently from x (unless it asked to bypass us anyway)
+fn unredirect_candidate(windows: &LinkedList, workspace: usize, config: &Config, monitor_areas: &[(Rect, Rect)]) -> Option<Window> {
+    let mut top: Option<CumWindow> = None;
+    let mut el = windows.index(0);
+    while let Some(element) = el {
+        let w = unsafe { (*element).value };
+        if !w.hide && on_workspace(&w, workspace) {
+            top = Some(w);
+        }
+        el = windows.next_element(element);
+    }
+    let w = top?;
+    let (monitor, _) = monitor_at(monitor_areas, w.x + w.width as i32 / 2, w.y + w.height as i32 / 2);
+    let covers_monitor = w.x <= monitor.x && w.y <= monitor.y
+        && w.x + w.width as i32 >= monitor.x + monitor.width && w.y + w.height as i32 >= monitor.y + monitor.height;
+    if !w.state.fullscreen || !covers_monitor || w.bypass_compositor == 2 {
+        return None;
+    }
+    let effects = w.has_alpha || w.opacity < 1.0 || window_shader(&w, config, true).is_some() || config.post_process_shader.is_some();
+    if effects && w.bypass_compositor != 1 {
+        return None;
+    }
+    Some(w.window_id)
+}
+
+fn border_theme(config: &Config, focused: bool) -> &Theme {
+    match &config.inactive_border_theme {
+        Some(theme) if !focused => theme,
+        _ => &config.border_theme,
+    }
+}
+
+// how to blur what's behind a window, if at all: only worth it if we can see through the window. region is what the
+// window set _KDE_NET_WM_BLUR_BEHIND_REGION to, which decides instead of the global setting when it's there;
+// rules beat both. no rectangles means the whole window
+fn blur_behind<'a>(w: &CumWindow, config: &Config, blur: Option<&'a Blur>, region: Option<&'a Vec<(i32, i32, i32, i32)>>) -> Option<(&'a Blur, &'a [(i32, i32, i32, i32)])> {
+    let translucent = w.has_alpha || w.opacity < 1.0;
+    let wanted = match region {
+        Some(_) => true,
+        None => config.blur,
+    };
+    if !translucent || !w.rule.blur.unwrap_or(wanted) {
+        return None;
+    }
+    Some((blur?, region.map_or(&[], |rects| rects.as_slice())))
+}
+
 fn main() {
     unsafe {
         XSetErrorHandler(Some(error_handler));
@@ -69,6 +606,7 @@ fn main() {
             return;
         }
     }
+    cache_atoms(display, &WATCHED_ATOMS);
     unsafe {
         XSync(display, 0);
     }
@@ -76,6 +614,15 @@ fn main() {
     println!("screen: {:?}", screen);
     println!("root: {:?}", root);
 
+    // `cwm --layout bsp` and friends talk to the cwm that's already running instead of starting another one
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if remote::run(display, root, &args) {
+        return;
+    }
+
+    let mut config = Config::load();
+    config.apply_args(args.into_iter());
+
     // get dimensions
     let mut src_width: c_int;
     let mut src_height: c_int;
@@ -115,6 +662,12 @@ fn main() {
     }
     println!("source dimensions: {:?}x{:?}", src_width, src_height);
 
+    // each monitor gets its own work area and wallpaper, and new windows open on the one with the pointer
+    let randr_event_base = setup_randr(display, root);
+    let mut monitors = find_monitors(display, root, randr_event_base.is_some(), src_width, src_height);
+    // set when the screen's size or monitors change, so everything screen sized gets made again
+    let mut screen_changed = false;
+
     let mut windows = LinkedList::new();
 
     //let mut accent_color;
@@ -131,55 +684,97 @@ fn main() {
         XSync(display, 0);
     }
 
-    let (desktop_id, desktop_picture) = setup_desktop(display, gc, screen, pict_format, root, src_width as u16, src_height as u16);
+    let (desktop_id, mut desktop_picture, mut desktop_pixmap) = setup_desktop(display, gc, screen, pict_format, root, src_width as u16, src_height as u16);
     println!("desktop id: {:?}", desktop_id);
 
+    let wm_check_window = setup_ewmh(display, root, &config, src_width as u32, src_height as u32);
+    // every managed window in the order they first showed up, for _NET_CLIENT_LIST
+    let mut client_list: Vec<Window> = Vec::new();
+    let mut current_workspace: usize = 0;
+
+    // every layout there is, and which one each workspace is using
+    let layouts = layout::builtin();
+    let mut workspace_layouts: Vec<usize> = (0..config.workspaces).map(|workspace| config.workspace_layout(workspace)).collect();
+    set_utf8_list_property(display, root, "_CWM_LAYOUT", &[layouts[workspace_layouts[0]].name().to_string()]);
+    // set whenever something happens that could change where tiled windows go
+    let mut need_retile = true;
+
     unsafe {
         XSync(display, 0);
     }
-    let mut now = SystemTime::now();
-    let mut t = 0;
+    let mut t: u64; // ticks since startup, for animated themes
     let mut need_redraw = true;
 
-    let fbconfig = unsafe { get_window_fb_config(desktop_id, display, screen) };
-    let desktop_window = CumWindow {
-        x: 0,
-        y: 0,
-        width: src_width as u16,
-        height: src_height as u16,
-        window_id: desktop_id,
-        frame_id: 0,
-        fbconfig,
-        hide: false,
-        has_alpha: true,
-        use_actual_position: false,
-        event: None,
-        velocity: XVelocity{
-            x_speed: 0.0,
-            last_x_location: 0,
-        }
-    };
 
     // rather use more memory than lose performance
 
     let mut frame_windows: Vec<Window> = Vec::new();
+    // override redirect windows (menus, tooltips and such), bottom to top; we draw them over everything we manage
+    let mut unmanaged_windows: Vec<CumWindow> = Vec::new();
     //let mut frame_windows_to_pick_up: Vec<Window> = Vec::new();
     let mut windows_to_destroy: Vec<Window> = Vec::new();
     let mut windows_to_configure: Vec<Window> = Vec::new();
     let mut windows_to_finally_move: Vec<Window> = Vec::new();
     let mut windows_to_open: Vec<Window> = Vec::new();
     let mut windows_to_hide: Vec<Window> = Vec::new();
+    // clients withdrawing a window also send a synthetic unmap notify (ICCCM 4.1.4), which is the only one we get
+    // if we'd already unmapped it for being on another workspace
+    let mut windows_withdrawn: Vec<Window> = Vec::new();
+    let mut windows_to_minimize: Vec<Window> = Vec::new();
+    let mut windows_to_update_opacity: Vec<Window> = Vec::new();
+    let mut windows_to_update_blur: Vec<Window> = Vec::new();
+    let mut windows_to_update_bypass: Vec<Window> = Vec::new();
+    let mut windows_to_update_strut: Vec<Window> = Vec::new();
+    // WM_TRANSIENT_FOR or _NET_WM_WINDOW_TYPE changed
+    let mut windows_to_update_type: Vec<Window> = Vec::new();
+    // _NET_WM_DESKTOP requests from pagers, None meaning sticky
+    let mut windows_to_move_workspace: Vec<(Window, Option<usize>)> = Vec::new();
+    let mut windows_to_toggle_floating: Vec<Window> = Vec::new();
+    // _NET_WM_STATE requests: the action (0 remove, 1 add, 2 toggle) and which state
+    let mut windows_to_change_state: Vec<(Window, c_long, Atom)> = Vec::new();
+    // set when above/below windows need putting back in their layer
+    let mut need_restack = false;
+    // where windows were before they went fullscreen or got maximized, keyed by client window id
+    let mut saved_geometries: HashMap<Window, (i32, i32, u16, u16)> = HashMap::new();
+    // clients we unmapped to shade them, so their unmap notify doesn't count as them going away
+    let mut shading_unmaps: Vec<Window> = Vec::new();
+    // the fullscreen window we stopped compositing for, if there is one
+    let mut unredirected_window: Option<Window> = None;
+    // where windows asked to be when they were made, until they're first mapped and we know whether to leave them there
+    let mut requested_positions: HashMap<Window, (i32, i32)> = HashMap::new();
+    // the last _NET_WORKAREA we set
+    let mut published_work_area: Option<Rect> = None;
+
+    // the window that gets keyboard input; everything else counts as inactive
+    let mut focused_window: Window = 0;
+    // what had focus on each workspace when we last left it, to give it back when we return
+    let mut workspace_focus: Vec<Window> = vec![0; config.workspaces];
+
+    // control point grids for wobbling, keyed by client window id
+    let mut wobbly_meshes: HashMap<Window, WobblyMesh> = HashMap::new();
+    // what windows set _KDE_NET_WM_BLUR_BEHIND_REGION to, keyed by client window id; missing if they didn't set it
+    let mut blur_regions: HashMap<Window, Vec<(i32, i32, i32, i32)>> = HashMap::new();
+    // last picture of every window, keyed by client window id
+    let mut window_textures: HashMap<Window, WindowTextures> = HashMap::new();
+    // running open/close/minimize/restore animations, keyed by client window id
+    let mut animations: HashMap<Window, Animation> = HashMap::new();
+    // destroyed windows that are still playing their closing animation
+    let mut closing_windows: Vec<ClosingWindow> = Vec::new();
+    let mut shadow_cache = ShadowCache::new(config.shadow_radius);
 
     let mut holding_window: Window = 0;
     let mut holding_window_x_offset: i32 = 0;
     let mut holding_window_y_offset: i32 = 0;
     let mut holding_window_x = 0;
     let mut holding_window_y = 0;
+    // everything that belongs to the held window and gets dragged along with it, worked out when it's picked up
+    let mut holding_transients: Vec<Window> = Vec::new();
+    // where the held window would be tiled if it was dropped right now
+    let mut snap_preview: Option<Rect> = None;
+    // sizes windows had before being tiled into an edge, so dragging them out again puts them back
+    let mut snap_restore_sizes: HashMap<Window, (u16, u16)> = HashMap::new();
     //let mut last_window_pickup_time;
 
-    let mut r= 0.0f64;
-    let mut g= 0.0f64;
-    let mut b= 0.0f64;
 
     //let mut cursor_x = 0;
     //let mut cursor_y = 0;
@@ -192,105 +787,43 @@ fn main() {
 
     let mut event: XEvent = unsafe { mem::zeroed() };
 
-    let shader_program;
-
-    unsafe {
-        let vertex_source = "
-#version 100
-attribute vec2 position;
-attribute vec3 color;
-attribute vec2 texcoord;
-
-varying vec3 Color;
-varying vec2 Texcoord;
-
-uniform mat4 projection;
-
-void main()
-{
-
-gl_Position = projection * vec4(position, 0.0, 1.0);
-Color = color;
-Texcoord = texcoord;
-}
-                  }";
-        let frag_source = "
-#version 100
-precision mediump float;
-
-varying vec3 Color;
-varying vec2 Texcoord;
-
-uniform sampler2D tex;
-
-
-void main()
-{
-gl_FragColor = texture2D(tex, Texcoord);
-}
-                }";
-        glCreateShader(GL_VERTEX_SHADER);
-        glShaderSource(GL_VERTEX_SHADER, 1, vertex_source.as_ptr() as *const *const c_char, null());
-        glCompileShader(GL_VERTEX_SHADER);
-        glCreateShader(GL_FRAGMENT_SHADER);
-        glShaderSource(GL_FRAGMENT_SHADER, 1, frag_source.as_ptr() as *const *const c_char, null());
-        glCompileShader(GL_FRAGMENT_SHADER);
-
-        shader_program = glCreateProgram();
-        glAttachShader(shader_program, GL_VERTEX_SHADER);
-        glAttachShader(shader_program, GL_FRAGMENT_SHADER);
-        glLinkProgram(shader_program);
-        glUseProgram(shader_program);
-
-        let position_loc = glGetAttribLocation(shader_program, "position".as_ptr() as *const i8);
-        let color_loc = glGetAttribLocation(shader_program, "color".as_ptr() as *const i8);
-        let texcoord_loc = glGetAttribLocation(shader_program, "texcoord".as_ptr() as *const i8);
-
-        let mut vao = 0;
-        glGenVertexArrays(1, &mut vao);
-        glBindVertexArray(vao);
-        glEnableVertexArrayAttrib(vao, position_loc as GLuint);
-        glEnableVertexArrayAttrib(vao, color_loc as GLuint);
-        glEnableVertexArrayAttrib(vao, texcoord_loc as GLuint);
-        glVertexArrayAttribBinding(vao, position_loc as GLuint, 0);
-        glVertexArrayAttribBinding(vao, color_loc as GLuint, 0);
-        glVertexArrayAttribBinding(vao, texcoord_loc as GLuint, 0);
-
-        glVertexArrayAttribFormat(vao, position_loc as GLuint, 2, GL_FLOAT, GL_FALSE as GLboolean, 0);
-        glVertexArrayAttribFormat(vao, color_loc as GLuint, 3, GL_FLOAT, GL_FALSE as GLboolean, 0);
-        glVertexArrayAttribFormat(vao, texcoord_loc as GLuint, 2, GL_FLOAT, GL_FALSE as GLboolean, 0);
-
-        let mut vbo = 0;
-        glGenBuffers(1, &mut vbo);
-        glBindBuffer(GL_ARRAY_BUFFER, vbo);
-
-        let texture_coords = [
-            0.0, 0.0,
-            1.0, 0.0,
-            0.0, 1.0,
-            1.0, 1.0
-        ];
-
-        glBufferData(GL_ARRAY_BUFFER, (mem::size_of::<f32>() * texture_coords.len() as usize) as GLsizeiptr, texture_coords.as_ptr() as *const c_void, GL_STATIC_DRAW);
+    // everything gets drawn through this, so there's nothing to do without it
+    let mut renderer = match Renderer::new(src_width, src_height) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            println!("could not set up the renderer: {}", e);
+            return;
+        }
+    };
+    let mut custom_shaders = CustomShaders::new(&config.shaders);
+    // needs the gl context for its textures, so it can't be set up with the desktop window
+    let mut background = Background::new(&config, src_width as u32, src_height as u32, &monitors);
+    let mut transitions = WorkspaceTransitions::new(config.workspace_animation_style, config.workspace_animation, src_width, src_height);
+
+    // blurring needs framebuffer objects and shaders; without them windows just don't get a blurred background
+    let mut blur = match Blur::new(src_width, src_height, config.blur_passes) {
+        Ok(blur) => Some(blur),
+        Err(e) => {
+            println!("could not set up blur: {}", e);
+            None
+        }
+    };
 
-        glViewport(0, 0, src_width as i32, src_height as i32);
-        glMatrixMode(GL_PROJECTION);
-        glLoadIdentity();
-        // make top left corner as origin
-        //glOrtho(0.0, src_width as f64, src_height as f64, 0.0, -1.0, 1.0);
-        gluOrtho2D(0.0, src_width as f64, src_height as f64, 0.0);
-        glEnable(GL_BLEND);
-        glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
-    }
+    // paces frames and measures how long each one was on screen, so wobbling keeps the same speed at any refresh rate
+    let refresh_rates = if randr_event_base.is_some() { get_refresh_rates(display, root) } else { Vec::new() };
+    let mut frame_clock = FrameClock::new(display, unsafe { XScreenNumberOfScreen(screen) }, overlay_window, &refresh_rates);
 
     loop {
         //println!("loop");
         unsafe {
             XFlush(display);
         }
-        let events_pending = unsafe { XEventsQueued(display, QueuedAlready as c_int) };
-        // if we have an event
-        if events_pending > 0 {
+        // reads whatever the server's sent since, without waiting for more
+        let events_pending = unsafe { XEventsQueued(display, QueuedAfterReading as c_int) };
+        // handle everything that's queued before deciding whether to draw: with vsync a frame is nearly always due,
+        // and one event per frame would leave the rest waiting behind the swap
+        let mut events_left = events_pending;
+        while events_left > 0 {
             unsafe {
                 XNextEvent(display, &mut event);
                 match event.type_ {
@@ -298,7 +831,7 @@ gl_FragColor = texture2D(tex, Texcoord);
                         let ev = event.xcreatewindow;
                         println!("new window!");
                         // check the parent window to see if it's the root window
-                        if root != ev.parent || overlay_window == ev.window || root == ev.window || desktop_id == ev.window {
+                        if root != ev.parent || overlay_window == ev.window || root == ev.window || desktop_id == ev.window || wm_check_window == ev.window {
                             println!("nevermind, it is root, desktop, or overlay");
                         } else {
                             // check if this is a frame window
@@ -307,14 +840,68 @@ gl_FragColor = texture2D(tex, Texcoord);
                                 println!("nvm it's a frame window");
                                 found = true;
                             }
-                            if !found {
-                                let centre_x = (src_width / 2) - (ev.width / 2);
-                                let centre_y = (src_height / 2) - (ev.height / 2);
-                                // change the main window to be in the centre of the screen
+                            if !found && ev.override_redirect != 0 {
+                                // menus, tooltips and popups that place themselves and don't want a window manager;
+                                // they still get drawn, at wherever they are, but that's all
+                                // if x can't tell us about it, it's gone again already
+                                let mut attribs: XWindowAttributes = mem::zeroed();
+                                if XGetWindowAttributes(display, ev.window, &mut attribs) != 0 {
+                                    let fbconfig = get_window_fb_config(ev.window, display, screen);
+                                    // xrender might not know the visual, and then it has no alpha channel we could use
+                                    let format = XRenderFindVisualFormat(display, attribs.visual);
+                                    let has_alpha = !format.is_null() && (*format).type_ == PictTypeDirect as c_int && (*format).direct.alphaMask != 0;
+                                    unmanaged_windows.push(CumWindow {
+                                        window_id: ev.window,
+                                        frame_id: 0,
+                                        x: ev.x,
+                                        y: ev.y,
+                                        width: ev.width as u16,
+                                        height: ev.height as u16,
+                                        // it might have been mapped before we got to the create notify
+                                        hide: attribs.map_state != IsViewable as c_int,
+                                        has_alpha,
+                                        fbconfig,
+                                        use_actual_position: true,
+                                        event: None,
+                                        velocity: XVelocity{
+                                            x_speed: 0.0,
+                                            last_x_location: ev.x,
+                                        },
+                                        minimized: false,
+                                        opacity: 1.0,
+                                        net_wm_opacity: 1.0,
+                                        rule: RuleSettings::default(),
+                                        window_type: get_window_type(display, ev.window),
+                                        workspace: None,
+                                        workspace_hidden: false,
+                                        floating: true,
+                                        state: WindowState::default(),
+                                        decorated: false,
+                                        strut: None,
+                                        transient_for: None,
+                                        bypass_compositor: 0,
+                                    });
+                                    need_redraw = true;
+                                }
+                            } else if !found {
+                                // the type is usually set by now, though it gets checked again once the window's mapped
+                                let window_type = get_window_type(display, ev.window);
+                                // panels, menus and the like go wherever they asked, everything else starts off in the middle of the free space
+                                // on the monitor with the pointer
+                                let screen_area = Rect::new(0, 0, src_width, src_height);
+                                let (pointer_x, pointer_y) = get_pointer_position(display, root);
+                                let (_, area) = monitor_at(&monitor_work_areas(&windows, &monitors, screen_area), pointer_x, pointer_y);
+                                let (place_x, place_y) = if window_type.keeps_position() {
+                                    (ev.x, ev.y)
+                                } else {
+                                    (area.x + area.width / 2 - ev.width / 2, area.y + area.height / 2 - ev.height / 2)
+                                };
+                                requested_positions.insert(ev.window, (ev.x, ev.y));
+                                set_cardinal_property(display, ev.window, "_NET_FRAME_EXTENTS", "CARDINAL", &expected_frame_extents(window_type));
                                 // configure window
                                 XConfigureWindow(display, ev.window, CWX | CWY, &mut XWindowChanges{
-                                    x: centre_x,
-                                    y: centre_y,
+                                    x: place_x,
+                                    y: place_y,
                                     width: ev.width as c_int,
                                     height: ev.height as c_int,
                                     border_width: 1,
@@ -324,7 +911,7 @@ gl_FragColor = texture2D(tex, Texcoord);
                                 // create the frame
                                 let frame_id =
                                     XCreateWindow(display, root,
-                                                  centre_x - 10, centre_y - 20,
+                                                  place_x - 10, place_y - 20,
                                                   ev.width as c_uint + 20, ev.height as c_uint + 25,
                                                   0, 24, InputOutput as c_uint,
                                                   CopyFromParent as *mut Visual, CWBackPixel as c_ulong, &mut XSetWindowAttributes{
@@ -344,11 +931,14 @@ gl_FragColor = texture2D(tex, Texcoord);
                                         colormap: 0,
                                         cursor: 0
                                     });
-                                // map the window
-                                XMapWindow(display, frame_id);
+                                // map the frame, if it's getting one
+                                if window_type.decorated() {
+                                    XMapWindow(display, frame_id);
+                                }
 
-                                // raise the actual window
+                                // raise the actual window, and then the popups back over it
                                 XRaiseWindow(display, ev.window);
+                                need_restack = true;
 
                                 // add to the list of frames
                                 frame_windows.push(frame_id);
@@ -356,7 +946,11 @@ gl_FragColor = texture2D(tex, Texcoord);
                                 let fbconfig = get_window_fb_config(ev.window, display, screen);
                                 let mut attribs : mem::MaybeUninit<XWindowAttributes> = mem::MaybeUninit::uninit();
                                 XGetWindowAttributes(display, ev.window, attribs.as_mut_ptr());
-                                let format = XRenderFindVisualFormat(display, attribs.assume_init().visual);
+                                let attribs = attribs.assume_init();
+                                let format = XRenderFindVisualFormat(display, attribs.visual);
+
+                                // we want to know when _NET_WM_WINDOW_OPACITY changes, without losing anything we'd already asked for
+                                XSelectInput(display, ev.window, attribs.your_event_mask | PropertyChangeMask as c_long);
                                 windows.push(CumWindow {
                                     window_id: ev.window,
                                     frame_id,
@@ -372,8 +966,27 @@ gl_FragColor = texture2D(tex, Texcoord);
                                     velocity: XVelocity{
                                         x_speed: 0.0,
                                         last_x_location: ev.x as i32,
-                                    }
+                                    },
+                                    minimized: false,
+                                    opacity: 1.0,
+                                    net_wm_opacity: 1.0,
+                                    rule: RuleSettings::default(),
+                                    window_type,
+                                    workspace: Some(current_workspace),
+                                    workspace_hidden: false,
+                                    floating: false,
+                                    state: WindowState::default(),
+                                    decorated: window_type.decorated(),
+                                    strut: None,
+                                    transient_for: None,
+                                    bypass_compositor: 0,
                                 }).expect("failed to add window");
+                                if window_type.wobbles() {
+                                    wobbly_meshes.insert(ev.window, WobblyMesh::new(
+                                        (place_x - 10) as f32, (place_y - 20) as f32,
+                                        (ev.width + 20) as f32, (ev.height + 25) as f32,
+                                        config.wobbly_grid_width, config.wobbly_grid_height));
+                                }
                                 need_redraw = true;
                             }
                         }
@@ -387,6 +1000,13 @@ gl_FragColor = texture2D(tex, Texcoord);
                             // remove from the list of frames
                             frame_windows.retain(|&x| x != ev.window);
                             need_redraw = true;
+                        } else if let Some(position) = unmanaged_windows.iter().position(|w| w.window_id == ev.window) {
+                            // popups just vanish, there's no closing animation for them
+                            unmanaged_windows.remove(position);
+                            if let Some(mut textures) = window_textures.remove(&ev.window) {
+                                textures.delete();
+                            }
+                            need_redraw = true;
                         } else {
                             // add to the list of windows to destroy
                             windows_to_destroy.push(ev.window);
@@ -397,12 +1017,28 @@ gl_FragColor = texture2D(tex, Texcoord);
                         let ev = event.xconfigure;
                         println!("configured window!");
                         // check if this is a frame window
-                        if !frame_windows.contains(&ev.window) {
+                        if let Some(position) = unmanaged_windows.iter().position(|w| w.window_id == ev.window) {
+                            // nothing to do but draw it wherever it went, in the order it stacked itself in. above is the
+                            // window just under it, and if that's not another popup then it's the lowest one
+                            let mut w = unmanaged_windows.remove(position);
+                            w.x = ev.x;
+                            w.y = ev.y;
+                            w.width = ev.width as u16;
+                            w.height = ev.height as u16;
+                            let below = unmanaged_windows.iter().position(|other| other.window_id == ev.above);
+                            let new_position = below.map_or(0, |below| below + 1);
+                            unmanaged_windows.insert(new_position, w);
+                            // it may have gone under windows we manage, which it has to stay above
+                            if new_position != position || below.is_none() {
+                                need_restack = true;
+                            }
+                            need_redraw = true;
+                        } else if !frame_windows.contains(&ev.window) {
                             // check if the window is the root window
                             if ev.window == root {
                                 src_height = ev.height;
                                 src_width = ev.width;
-                                // todo: resize the sdl window (do we still need to do this?)
+                                screen_changed = true;
                             }
                             /*let fbconfig = get_window_fb_config(ev.window, display, screen);
                             let mut attribs : mem::MaybeUninit<XWindowAttributes> = mem::MaybeUninit::uninit();
@@ -422,7 +1058,13 @@ gl_FragColor = texture2D(tex, Texcoord);
                     19 => { // map notify
                         // add to windows to open
                         println!("map notify");
-                        if !frame_windows.contains(event.xmap.window.borrow()) {
+                        if let Some(position) = unmanaged_windows.iter().position(|w| w.window_id == event.xmap.window) {
+                            // whatever popped up last goes on top of the other popups
+                            let mut w = unmanaged_windows.remove(position);
+                            w.hide = false;
+                            unmanaged_windows.push(w);
+                            need_redraw = true;
+                        } else if !frame_windows.contains(event.xmap.window.borrow()) {
                             windows_to_open.push(event.xmap.window);
 
                             need_redraw = true;
@@ -438,7 +1080,13 @@ gl_FragColor = texture2D(tex, Texcoord);
                     18 => { // unmapnotify
                         // add to windows to close
                         println!("unmap notify");
-                        if !frame_windows.contains(event.xmap.window.borrow()) {
+                        if let Some(w) = unmanaged_windows.iter_mut().find(|w| w.window_id == event.xunmap.window) {
+                            w.hide = true;
+                            need_redraw = true;
+                        } else if event.xunmap.send_event != 0 {
+                            windows_withdrawn.push(event.xunmap.window);
+                            need_redraw = true;
+                        } else if !frame_windows.contains(event.xmap.window.borrow()) {
                             windows_to_hide.push(event.xunmap.window);
 
                             need_redraw = true;
@@ -507,52 +1155,269 @@ gl_FragColor = texture2D(tex, Texcoord);
                         XSendEvent(display, ev.window, 0, 0, &mut event);
                         XFlush(display);
                     },
+                    28 => { // property notify
+                        let ev = event.xproperty;
+                        if ev.atom == get_atom(display, "_NET_WM_WINDOW_OPACITY") {
+                            windows_to_update_opacity.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.atom == get_atom(display, "_KDE_NET_WM_BLUR_BEHIND_REGION") {
+                            windows_to_update_blur.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.atom == get_atom(display, "_NET_WM_STRUT_PARTIAL") || ev.atom == get_atom(display, "_NET_WM_STRUT") {
+                            windows_to_update_strut.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.atom == get_atom(display, "_NET_WM_BYPASS_COMPOSITOR") {
+                            windows_to_update_bypass.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.atom == get_atom(display, "WM_TRANSIENT_FOR") || ev.atom == get_atom(display, "_NET_WM_WINDOW_TYPE") {
+                            windows_to_update_type.push(ev.window);
+                            need_redraw = true;
+                        }
+                    },
+                    33 => { // client message
+                        let ev = event.xclient;
+                        // iconify request from a taskbar or the client itself (ICCCM 4.1.4)
+                        if ev.message_type == get_atom(display, "WM_CHANGE_STATE") && ev.data.l[0] == IconicState as c_long {
+                            println!("minimize request");
+                            windows_to_minimize.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.message_type == get_atom(display, "_NET_CURRENT_DESKTOP") {
+                            // a pager switching workspaces; the windows get shown and hidden when we next redraw
+                            let workspace = ev.data.l[0] as usize;
+                            if workspace < config.workspaces && workspace != current_workspace {
+                                println!("switching to workspace {}", workspace);
+                                transitions.start(current_workspace, workspace);
+                                workspace_focus[current_workspace] = focused_window;
+                                current_workspace = workspace;
+                                // sticky windows are still mapped and can have focus back straight away, the rest get it
+                                // once they're mapped again
+                                if let Some(w) = find_window(&windows, workspace_focus[workspace]).filter(|w| !w.hide) {
+                                    focused_window = w.window_id;
+                                    XSetInputFocus(display, w.window_id, RevertToPointerRoot as c_int, CurrentTime as c_ulong);
+                                }
+                                set_cardinal_property(display, root, "_NET_CURRENT_DESKTOP", "CARDINAL", &[workspace as u64]);
+                                background.set_workspace(workspace);
+                                set_utf8_list_property(display, root, "_CWM_LAYOUT", &[layouts[workspace_layouts[workspace]].name().to_string()]);
+                                need_redraw = true;
+                                need_retile = true;
+                            }
+                        } else if ev.message_type == get_atom(display, "_NET_WM_DESKTOP") {
+                            let workspace = workspace_from_cardinal(ev.data.l[0] as u64);
+                            if workspace.map_or(true, |workspace| workspace < config.workspaces) {
+                                windows_to_move_workspace.push((ev.window, workspace));
+                                need_redraw = true;
+                                need_retile = true;
+                            }
+                        } else if ev.message_type == get_atom(display, "_CWM_LAYOUT") {
+                            // from cwm --layout: the atom for the layout's name, or 0 for the next one
+                            let current = workspace_layouts[current_workspace];
+                            let wanted = match ev.data.l[0] {
+                                0 => Some((current + 1) % layouts.len()),
+                                atom => get_atom_name(display, atom as Atom).and_then(|name| layout::index_of(&name)),
+                            };
+                            if let Some(wanted) = wanted {
+                                println!("switching to the {} layout", layouts[wanted].name());
+                                workspace_layouts[current_workspace] = wanted;
+                                set_utf8_list_property(display, root, "_CWM_LAYOUT", &[layouts[wanted].name().to_string()]);
+                                need_redraw = true;
+                                need_retile = true;
+                            }
+                        } else if ev.message_type == get_atom(display, "_CWM_TOGGLE_FLOATING") {
+                            windows_to_toggle_floating.push(ev.window);
+                            need_redraw = true;
+                        } else if ev.message_type == get_atom(display, "_NET_WM_STATE") {
+                            // up to two states at once, the second one is 0 if there's only one
+                            for property in [ev.data.l[1], ev.data.l[2]] {
+                                if property != 0 {
+                                    windows_to_change_state.push((ev.window, ev.data.l[0], property as Atom));
+                                }
+                            }
+                            need_redraw = true;
+                            need_retile = true;
+                        } else if ev.message_type == get_atom(display, "_NET_REQUEST_FRAME_EXTENTS") {
+                            // asked before the window's mapped, so guess from its type like we will when it is
+                            let extents = expected_frame_extents(get_window_type(display, ev.window));
+                            set_cardinal_property(display, ev.window, "_NET_FRAME_EXTENTS", "CARDINAL", &extents);
+                        }
+                    },
                     30 => { // selection request (i don't know what this does so just pass it along)
                         println!("selection request");
                         let ev = event.xselectionrequest;
                         XSendEvent(display, ev.owner, 0, 0, &mut event);
                         XFlush(display);
                     },
+                    type_ if randr_event_base.map_or(false, |base| type_ == base + RRScreenChangeNotify as c_int) => {
+                        println!("screen change notify");
+                        // so xlib knows the screen's new size too
+                        XRRUpdateConfiguration(&mut event);
+                        screen_changed = true;
+                    },
                     _ => {
                         println!("unhandled event");
                         println!("{:?}", event.type_);
                     }
                 }
             }
+            events_left = unsafe { XEventsQueued(display, QueuedAlready as c_int) };
+        }
+
+        // themes animate once per tick, and something's always moving, so every frame gets drawn
+        t = frame_clock.ticks();
+        if frame_clock.frame_due() {
+            need_redraw = true;
+        }
+
+        // monitors got plugged in, unplugged or rearranged, so everything screen sized has to follow
+        if screen_changed {
+            screen_changed = false;
+            unsafe {
+                let mut attr: XWindowAttributes = mem::zeroed();
+                if XGetWindowAttributes(display, root, &mut attr) != 0 {
+                    src_width = attr.width;
+                    src_height = attr.height;
+                } else {
+                    println!("couldn't get the new size of the root window, staying at {}x{}", src_width, src_height);
+                }
+                XResizeWindow(display, overlay_window, src_width as c_uint, src_height as c_uint);
+            }
+            monitors = find_monitors(display, root, randr_event_base.is_some(), src_width, src_height);
+            if randr_event_base.is_some() {
+                frame_clock.set_refresh_rates(&get_refresh_rates(display, root));
+            }
+            println!("screen is now {}x{} with {} monitor(s)", src_width, src_height, monitors.len());
+            renderer.resize(src_width, src_height);
+            if let Some(blur) = &mut blur {
+                blur.resize(src_width, src_height);
+            }
+            transitions.resize(src_width, src_height);
+            (desktop_picture, desktop_pixmap) = resize_desktop(display, screen, root, desktop_id, desktop_picture, desktop_pixmap,
+                                                               src_width as u16, src_height as u16);
+            // the new desktop pixmap gets the wallpaper through take_changed below
+            background.resize(src_width as u32, src_height as u32, &monitors);
+            set_cardinal_property(display, root, "_NET_DESKTOP_GEOMETRY", "CARDINAL", &[src_width as u64, src_height as u64]);
+            // windows left on a monitor that's gone come over to the first one. find_monitors falls back to the whole
+            // screen when xrandr has nothing, so there always is one
+            let first = monitors[0];
+            let mut el = windows.index(0);
+            let mut i = 0;
+            while let Some(element) = el {
+                let mut w = unsafe { (*element).value };
+                el = windows.next_element(element);
+                let (centre_x, centre_y) = (w.x + w.width as i32 / 2, w.y + w.height as i32 / 2);
+                if !w.window_type.keeps_position() && !monitors.iter().any(|monitor| monitor.contains(centre_x, centre_y)) {
+                    w.x = first.x + (first.width - w.width as i32) / 2;
+                    w.y = first.y + (first.height - w.height as i32) / 2;
+                    unsafe {
+                        XMoveWindow(display, w.window_id, w.x, w.y);
+                        XMoveWindow(display, w.frame_id, w.x - 10, w.y - 20);
+                    }
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                }
+                i += 1;
+            }
+            // and maximized windows and tiled ones get fitted to the new work areas
+            published_work_area = None;
+            need_retile = true;
+            need_redraw = true;
         }
 
-        let after = SystemTime::now();
-        if after.duration_since(now).unwrap().as_millis() > (1/60) as u128 {
-            // generate the rainbow using a sine wave
-            let frequency = 0.05;
-            r = (frequency * (t as f64) + 0.0).sin() * 127.0f64 + 128.0f64;
-            g = (frequency * (t as f64) + 2.0).sin() * 127.0f64 + 128.0f64;
-            b = (frequency * (t as f64) + 4.0).sin() * 127.0f64 + 128.0f64;
+        // slideshows and crossfades
+        if background.update() {
+            need_redraw = true;
+        }
+        if let Some(image) = background.take_changed() {
+            // the desktop window and root pixmap are what other programs see, so keep them up to date too
+            put_wallpaper(display, gc, screen, desktop_pixmap, &image);
+            redraw_desktop(display, desktop_picture, desktop_id, pict_format, src_width as u32, src_height as u32);
+        }
 
-            //accent_color = ((((r as u32) << 16) | ((g as u32) << 8) | (b as u32)) | 0xFF000000) as u32;
-            t += 1;
+        // an opaque fullscreen window with nothing going on over it can skip us and go straight to the screen,
+        // until anything else needs drawing again
+        let busy = need_retile || need_restack || holding_window != 0 || transitions.is_running() || !animations.is_empty()
+            || unmanaged_windows.iter().any(|w| !w.hide) || !closing_windows.is_empty() || !windows_to_open.is_empty() || !windows_to_hide.is_empty() || !windows_to_minimize.is_empty()
+            || !windows_to_destroy.is_empty() || !windows_to_change_state.is_empty() || !windows_to_move_workspace.is_empty()
+            || !windows_to_toggle_floating.is_empty() || !windows_to_update_opacity.is_empty() || !windows_to_update_bypass.is_empty()
+            || !windows_to_update_strut.is_empty() || !windows_to_update_type.is_empty() || !windows_withdrawn.is_empty();
+        let bypass = if config.unredirect_fullscreen && !busy {
+            let screen_area = Rect { x: 0, y: 0, width: src_width, height: src_height };
+            unredirect_candidate(&windows, current_workspace, &config, &monitor_work_areas(&windows, &monitors, screen_area))
+        } else {
+            None
+        };
+        if bypass != unredirected_window {
+            if bypass.is_some() != unredirected_window.is_some() {
+                println!("{} compositing", if bypass.is_some() { "pausing" } else { "resuming" });
+                set_redirected(display, root, overlay_window, bypass.is_none());
+            }
+            unredirected_window = bypass;
             need_redraw = true;
-            now = after;
+        }
+        if unredirected_window.is_some() {
+            need_redraw = false;
         }
 
-        if need_redraw {
+        if need_retile {
+            need_retile = false;
+            let screen_area = Rect::new(0, 0, src_width, src_height);
+            let monitor_areas = monitor_work_areas(&windows, &monitors, screen_area);
+            retile(display, &mut windows, layouts[workspace_layouts[current_workspace]].as_ref(), &config.tiling, &monitor_areas,
+                   current_workspace, holding_window);
+        }
+
+        // events can ask for a redraw whenever, but it waits for the next frame so nothing gets drawn more often than it's shown
+        if need_redraw && frame_clock.frame_due() {
+            let dt = frame_clock.dt();
+            let wallpaper_color = background.color();
+            let screen_area = Rect::new(0, 0, src_width, src_height);
+            let usable_area = work_area(&windows, screen_area, screen_area);
+            let monitor_areas = monitor_work_areas(&windows, &monitors, screen_area);
+            // panels tell other programs (and maximized windows) how much room is left
+            let work_area_changed = published_work_area != Some(usable_area);
+            if work_area_changed {
+                published_work_area = Some(usable_area);
+                let area = [usable_area.x as u64, usable_area.y as u64, usable_area.width as u64, usable_area.height as u64];
+                set_cardinal_property(display, root, "_NET_WORKAREA", "CARDINAL", &area.repeat(config.workspaces));
+                need_retile = true;
+            }
+            custom_shaders.reload_changed();
+            renderer.begin_frame(custom_shaders.program(config.post_process_shader));
             //println!("redrawing");
             unsafe {
-                glClearColor((r/255.0f64) as GLclampf, (g/255.0f64) as GLclampf, (b/255.0f64) as GLclampf, 1.0);
+                let [r, g, b] = config.background_theme.color_at(t, wallpaper_color, false);
+                glClearColor(r as GLclampf, g as GLclampf, b as GLclampf, 1.0);
                 glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
-
-                /*
-                glMatrixMode(GL_PROJECTION);
-                glLoadIdentity();
-                glOrtho(-1.0, 1.0, -1.0, 1.0, 1.0, 20.0);
-
-                 */
             }
 
             // draw the desktop
 
-            draw_x_window(desktop_window, false, display, shader_program,
-                          true, src_width as u32, src_height as u32,0,0,0);
+            background.draw(&mut renderer);
+            transitions.begin_frame(&mut renderer);
+
+            // frames of everything a held window can snap to
+            let mut snap_targets: Vec<Rect> = Vec::new();
+            if holding_window != 0 {
+                let mut el = windows.index(0);
+                while let Some(element) = el {
+                    let w = unsafe { (*element).value };
+                    if !w.hide && w.window_id != holding_window && on_workspace(&w, current_workspace) {
+                        snap_targets.push(Rect { x: w.x - 10, y: w.y - 20, width: w.width as i32 + 20, height: w.height as i32 + 25 });
+                    }
+                    el = windows.next_element(element);
+                }
+            } else {
+                snap_preview = None;
+            }
+
+            // transients go along with the window they belong to when it's minimized or sent to another workspace
+            with_transients(&windows, &mut windows_to_minimize);
+            for (window, workspace) in windows_to_move_workspace.clone() {
+                for j in transients_of(&windows, window) {
+                    let transient = unsafe { (*windows.index(j).unwrap()).value.window_id };
+                    if !windows_to_move_workspace.iter().any(|(queued, _)| *queued == transient) {
+                        windows_to_move_workspace.push((transient, workspace));
+                    }
+                }
+            }
 
             let mut el = windows.index(0);
             let mut i = 0;
@@ -562,22 +1427,395 @@ gl_FragColor = texture2D(tex, Texcoord);
                 }
                 let mut w = unsafe { (*el.unwrap()).value };
                 // if we need to destroy this window, do so
+                // start animations from wherever the window currently is, so interrupting one doesn't make it jump
+                let current_transform = animations.get(&w.window_id).map(|a| a.current());
                 if windows_to_open.contains(&w.window_id) {
                     println!("completely opening window");
-                    let mut window = unsafe { (*el.unwrap()).value };
-                    window.hide = false;
-                    windows.change_element_at_index(i, window).expect("Error changing window");
+                    // expose events end up here too, so only animate if the window was actually hidden.
+                    // windows coming back with their workspace don't either, the transition already shows them arriving
+                    if w.hide && !(w.workspace_hidden && transitions.is_running()) {
+                        let kind = if w.minimized { AnimationKind::Restore } else { AnimationKind::Map };
+                        let from = match current_transform {
+                            Some(transform) => transform,
+                            None if w.minimized => Transform::towards_rect(
+                                ((w.x - 10) as f32, (w.y - 20) as f32, (w.width + 20) as f32, (w.height + 25) as f32),
+                                minimize_target(display, w.window_id, src_width, src_height)),
+                            None => Transform::HIDDEN,
+                        };
+                        animations.insert(w.window_id, Animation::new(kind, from, Transform::IDENTITY, config.animation_settings(kind)));
+                        if w.minimized {
+                            // fullscreen and undecorated windows never have their frame mapped
+                            if shows_frame(&w) {
+                                unsafe {
+                                    XMapWindow(display, w.frame_id);
+                                }
+                            }
+                            // and bring back whatever got minimized along with it. only their clients get mapped here: each one
+                            // comes back through its own map notify, which maps its frame and clears its minimized flag like above
+                            for j in transients_of(&windows, w.window_id) {
+                                let transient = unsafe { (*windows.index(j).unwrap()).value };
+                                if transient.minimized {
+                                    unsafe {
+                                        XMapWindow(display, transient.window_id);
+                                    }
+                                }
+                            }
+                            w.minimized = false;
+                        }
+                    }
+                    if w.hide {
+                        // WM_CLASS and the opacity are usually set by the time the window gets mapped
+                        let (instance, class) = get_window_class(display, w.window_id);
+                        w.rule = config.rule_for(&instance, &class);
+                        w.window_type = get_window_type(display, w.window_id);
+                        match get_blur_region(display, w.window_id) {
+                            Some(region) => blur_regions.insert(w.window_id, region),
+                            None => blur_regions.remove(&w.window_id),
+                        };
+                        w.net_wm_opacity = get_window_opacity(display, w.window_id);
+                        w.bypass_compositor = get_cardinal_property(display, w.window_id, "_NET_WM_BYPASS_COMPOSITOR").map_or(0, |values| values[0]);
+                        let returning_with_workspace = w.workspace_hidden;
+                        w.workspace_hidden = false;
+                        w.strut = get_strut(display, w.window_id);
+                        w.decorated = w.window_type.decorated();
+                        if !w.window_type.wobbles() {
+                            wobbly_meshes.remove(&w.window_id);
+                        }
+                        if !client_list.contains(&w.window_id) {
+                            client_list.push(w.window_id);
+                            publish_client_list(display, root, &client_list);
+                        }
+                        // the first time it's shown, now that we know what kind of window it is
+                        if let Some((requested_x, requested_y)) = requested_positions.remove(&w.window_id) {
+                            w.transient_for = transient_parent(display, root, w.window_id);
+                            let parent = w.transient_for.and_then(|parent| find_window(&windows, parent));
+                            // a position the user gave (or the program did, if it's not just the origin) is kept, e.g. from restoring a session
+                            let (hint_flags, gravity) = get_normal_hints(display, w.window_id);
+                            let picked_position = hint_flags & USPosition as c_long != 0
+                                || (hint_flags & PPosition as c_long != 0 && (requested_x, requested_y) != (0, 0));
+                            if w.window_type.keeps_position() {
+                                // we centred it before we knew better; the frame follows on the configure notify
+                                w.x = requested_x;
+                                w.y = requested_y;
+                                unsafe {
+                                    XMoveWindow(display, w.window_id, w.x, w.y);
+                                }
+                            } else if let Some(parent) = parent {
+                                // dialogs go in the middle of the window they're for
+                                w.x = parent.x + (parent.width as i32 - w.width as i32) / 2;
+                                w.y = parent.y + (parent.height as i32 - w.height as i32) / 2;
+                                unsafe {
+                                    XMoveWindow(display, w.window_id, w.x, w.y);
+                                    XMoveWindow(display, w.frame_id, w.x - 10, w.y - 20);
+                                }
+                            } else if picked_position {
+                                // the gravity says which part of the frame goes where it asked, so it doesn't creep by the frame size every launch
+                                (w.x, w.y) = apply_gravity(gravity, frame_extents(&w), requested_x, requested_y);
+                                unsafe {
+                                    XMoveWindow(display, w.window_id, w.x, w.y);
+                                    XMoveWindow(display, w.frame_id, w.x - 10, w.y - 20);
+                                }
+                            }
+                            // panels and the desktop are on every workspace, and transients go wherever their parent is
+                            if matches!(w.window_type, WindowType::Dock | WindowType::Desktop) {
+                                w.workspace = None;
+                            } else if let Some(parent) = parent {
+                                w.workspace = parent.workspace;
+                            }
+                            // and dialogs float over tiled windows unless a rule says otherwise
+                            w.floating = parent.is_some() || matches!(w.window_type, WindowType::Dialog | WindowType::Utility);
+                        }
+                        if let Some(floating) = w.rule.floating {
+                            w.floating = floating;
+                        }
+                        // clients can ask for a workspace before mapping (session restore does this), otherwise they stay where they were made
+                        let wanted_workspace = get_cardinal_property(display, w.window_id, "_NET_WM_DESKTOP")
+                            .map(|values| workspace_from_cardinal(values[0]));
+                        if let Some(workspace) = wanted_workspace {
+                            if workspace.map_or(true, |workspace| workspace < config.workspaces) {
+                                w.workspace = workspace;
+                            }
+                        }
+                        set_cardinal_property(display, w.window_id, "_NET_WM_DESKTOP", "CARDINAL", &[workspace_to_cardinal(w.workspace)]);
+                        // a shaded client mapping itself again (after being restored, say) isn't shaded anymore
+                        if w.state.shaded {
+                            w.state.shaded = false;
+                            apply_state(display, &mut w, &mut saved_geometries, &monitor_areas);
+                        }
+                        // and ones that aren't managed yet can ask to start fullscreen, maximized and so on
+                        if w.state == WindowState::default() {
+                            let names = get_cardinal_property(display, w.window_id, "_NET_WM_STATE").unwrap_or_default()
+                                .into_iter().filter_map(|atom| get_atom_name(display, atom as Atom));
+                            for name in names {
+                                if name == "_NET_WM_STATE_STICKY" {
+                                    w.workspace = None;
+                                } else if let Some(flag) = w.state.flag(&name) {
+                                    *flag = true;
+                                }
+                            }
+                            // nothing to shade before there's anything to see
+                            w.state.shaded = false;
+                            if w.state.fullscreen || w.state.maximized() {
+                                apply_state(display, &mut w, &mut saved_geometries, &monitor_areas);
+                            }
+                        }
+                        if stacking_layer(&w) != 0 || w.transient_for.is_some() {
+                            need_restack = true;
+                        }
+                        unsafe {
+                            if shows_frame(&w) {
+                                XMapWindow(display, w.frame_id);
+                            } else {
+                                XUnmapWindow(display, w.frame_id);
+                            }
+                        }
+                        publish_state(display, &w);
+                        let actions: Vec<u64> = ALLOWED_ACTIONS.iter().map(|action| get_atom(display, action) as u64).collect();
+                        set_cardinal_property(display, w.window_id, "_NET_WM_ALLOWED_ACTIONS", "ATOM", &actions);
+                        // windows opening on another workspace get hidden again below, so don't give them focus. ones coming
+                        // back with their workspace only get it if they had it when we left
+                        let wants_focus = if returning_with_workspace {
+                            workspace_focus[current_workspace] == w.window_id
+                        } else {
+                            w.window_type.takes_focus()
+                        };
+                        if on_workspace(&w, current_workspace) && wants_focus {
+                            focused_window = w.window_id;
+                            unsafe {
+                                XSetInputFocus(display, w.window_id, RevertToPointerRoot as c_int, CurrentTime as c_ulong);
+                            }
+                        }
+                    }
+                    if w.hide {
+                        need_retile = true;
+                        publish_wm_state(display, w.window_id, NormalState);
+                    }
+                    w.hide = false;
+                    windows.change_element_at_index(i, w).expect("Error changing window");
                     windows_to_open.retain(|x| x != &w.window_id);
+                } else if windows_to_minimize.contains(&w.window_id) {
+                    println!("completely minimizing window");
+                    if !w.hide {
+                        let to = Transform::towards_rect(
+                            ((w.x - 10) as f32, (w.y - 20) as f32, (w.width + 20) as f32, (w.height + 25) as f32),
+                            minimize_target(display, w.window_id, src_width, src_height));
+                        animations.insert(w.window_id, Animation::new(AnimationKind::Minimize, current_transform.unwrap_or(Transform::IDENTITY),
+                                                                      to, config.animation_settings(AnimationKind::Minimize)));
+                        w.minimized = true;
+                        w.hide = true;
+                        need_retile = true;
+                        publish_state(display, &w);
+                        publish_wm_state(display, w.window_id, IconicState);
+                        unsafe {
+                            XUnmapWindow(display, w.window_id);
+                            XUnmapWindow(display, w.frame_id);
+                        }
+                        windows.change_element_at_index(i, w).expect("Error changing window");
+                    }
+                    windows_to_minimize.retain(|x| x != &w.window_id);
                 } else if windows_to_hide.contains(&w.window_id) {
                     println!("completely hiding window");
-                    let mut window = unsafe { (*el.unwrap()).value };
-                    window.hide = true;
-                    windows.change_element_at_index(i, window).expect("Error changing window");
+                    if let Some(position) = shading_unmaps.iter().position(|&window| window == w.window_id) {
+                        // we unmapped it to shade it, it's still there
+                        shading_unmaps.remove(position);
+                    } else {
+                        // minimizing hides the window itself, so this only animates windows that unmapped on their own
+                        if !w.hide {
+                            animations.insert(w.window_id, Animation::new(AnimationKind::Unmap, current_transform.unwrap_or(Transform::IDENTITY),
+                                                                          Transform::HIDDEN, config.animation_settings(AnimationKind::Unmap)));
+                            need_retile = true;
+                        }
+                        w.hide = true;
+                    }
+                    windows.change_element_at_index(i, w).expect("Error changing window");
                     windows_to_hide.retain(|x| x != &w.window_id);
                 }
+                if windows_withdrawn.contains(&w.window_id) {
+                    // it's the client's to map again now, not something to bring back with its workspace
+                    w.workspace_hidden = false;
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_withdrawn.retain(|x| x != &w.window_id);
+                }
+                if windows_to_update_opacity.contains(&w.window_id) {
+                    w.net_wm_opacity = get_window_opacity(display, w.window_id);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_update_opacity.retain(|x| x != &w.window_id);
+                }
+                if windows_to_update_blur.contains(&w.window_id) {
+                    match get_blur_region(display, w.window_id) {
+                        Some(region) => blur_regions.insert(w.window_id, region),
+                        None => blur_regions.remove(&w.window_id),
+                    };
+                    windows_to_update_blur.retain(|x| x != &w.window_id);
+                }
+                if windows_to_update_strut.contains(&w.window_id) {
+                    // a panel resizing or moving; everything else adjusts once the work area's worked out again
+                    w.strut = get_strut(display, w.window_id);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_update_strut.retain(|x| x != &w.window_id);
+                }
+                if windows_to_update_type.contains(&w.window_id) {
+                    // the first map reads both anyway, so this is only for windows that have been shown before
+                    if !requested_positions.contains_key(&w.window_id) {
+                        w.transient_for = transient_parent(display, root, w.window_id);
+                        // hidden windows get their type read again when they're mapped
+                        if !w.hide {
+                            w.window_type = get_window_type(display, w.window_id);
+                            w.decorated = w.window_type.decorated();
+                            if !w.window_type.wobbles() {
+                                wobbly_meshes.remove(&w.window_id);
+                            }
+                            unsafe {
+                                if shows_frame(&w) {
+                                    XMapWindow(display, w.frame_id);
+                                } else {
+                                    XUnmapWindow(display, w.frame_id);
+                                }
+                            }
+                            set_cardinal_property(display, w.window_id, "_NET_FRAME_EXTENTS", "CARDINAL", &frame_extents(&w));
+                        }
+                        windows.change_element_at_index(i, w).expect("Error changing window");
+                        need_restack = true;
+                        need_retile = true;
+                    }
+                    windows_to_update_type.retain(|x| x != &w.window_id);
+                }
+                // maximized windows fill whatever the panels leave
+                if work_area_changed && w.state.maximized() && !w.state.fullscreen {
+                    apply_state(display, &mut w, &mut saved_geometries, &monitor_areas);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                }
+                if windows_to_update_bypass.contains(&w.window_id) {
+                    w.bypass_compositor = get_cardinal_property(display, w.window_id, "_NET_WM_BYPASS_COMPOSITOR").map_or(0, |values| values[0]);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_update_bypass.retain(|x| x != &w.window_id);
+                }
+                if windows_to_toggle_floating.contains(&w.window_id) {
+                    w.floating = !w.floating;
+                    println!("window is {}", if w.floating { "floating" } else { "tiled" });
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_toggle_floating.retain(|x| x != &w.window_id);
+                    need_retile = true;
+                }
+                let state_changes: Vec<(c_long, Atom)> = windows_to_change_state.iter()
+                    .filter(|(window, _, _)| *window == w.window_id).map(|&(_, action, atom)| (action, atom)).collect();
+                if !state_changes.is_empty() {
+                    let before = w.state;
+                    for (action, atom) in state_changes {
+                        let name = match get_atom_name(display, atom) {
+                            Some(name) => name,
+                            None => continue,
+                        };
+                        println!("{} {}", match action { 0 => "removing", 1 => "adding", _ => "toggling" }, name);
+                        // sticky is the same as being on every workspace; hidden is ours to set, not the client's (ewmh says to ignore it)
+                        if name == "_NET_WM_STATE_STICKY" {
+                            let sticky = match action { 0 => false, 1 => true, _ => w.workspace.is_some() };
+                            w.workspace = if sticky { None } else { Some(w.workspace.unwrap_or(current_workspace)) };
+                            set_cardinal_property(display, w.window_id, "_NET_WM_DESKTOP", "CARDINAL", &[workspace_to_cardinal(w.workspace)]);
+                        } else if let Some(flag) = w.state.flag(&name) {
+                            *flag = match action { 0 => false, 1 => true, _ => !*flag };
+                        }
+                    }
+                    // fullscreen and undecorated windows have no title bar to roll up into
+                    if w.state.fullscreen || !w.decorated {
+                        w.state.shaded = false;
+                    }
+                    // the client gets unmapped while it's shaded, leaving just the frame
+                    if w.state.shaded != before.shaded && !w.hide {
+                        unsafe {
+                            if w.state.shaded {
+                                shading_unmaps.push(w.window_id);
+                                XUnmapWindow(display, w.window_id);
+                            } else {
+                                XMapWindow(display, w.window_id);
+                            }
+                        }
+                    }
+                    if (w.state.fullscreen, w.state.maximized_vert, w.state.maximized_horz, w.state.shaded)
+                        != (before.fullscreen, before.maximized_vert, before.maximized_horz, before.shaded) {
+                        apply_state(display, &mut w, &mut saved_geometries, &monitor_areas);
+                    }
+                    if stacking_layer(&w) != stacking_layer(&before) {
+                        need_restack = true;
+                    }
+                    publish_state(display, &w);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_change_state.retain(|(window, _, _)| *window != w.window_id);
+                }
+                if let Some(&(_, workspace)) = windows_to_move_workspace.iter().find(|(window, _)| *window == w.window_id) {
+                    w.workspace = workspace;
+                    set_cardinal_property(display, w.window_id, "_NET_WM_DESKTOP", "CARDINAL", &[workspace_to_cardinal(workspace)]);
+                    publish_state(display, &w);
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                    windows_to_move_workspace.retain(|(window, _)| *window != w.window_id);
+                }
+                // windows on other workspaces get unmapped, and mapped again once their workspace comes back.
+                // minimized and withdrawn windows are already unmapped, so they're left alone
+                if !on_workspace(&w, current_workspace) && !w.hide {
+                    println!("hiding window on another workspace");
+                    unsafe {
+                        XUnmapWindow(display, w.window_id);
+                        XUnmapWindow(display, w.frame_id);
+                    }
+                    w.hide = true;
+                    w.workspace_hidden = true;
+                    if focused_window == w.window_id {
+                        focused_window = 0;
+                        unsafe {
+                            XSetInputFocus(display, root, RevertToPointerRoot as c_int, CurrentTime as c_ulong);
+                        }
+                    }
+                    windows.change_element_at_index(i, w).expect("Error changing window");
+                } else if on_workspace(&w, current_workspace) && w.workspace_hidden {
+                    if w.state.shaded {
+                        // the client stays unmapped, so there's no map notify to wait for
+                        unsafe {
+                            XMapWindow(display, w.frame_id);
+                        }
+                        w.hide = false;
+                        w.workspace_hidden = false;
+                        windows.change_element_at_index(i, w).expect("Error changing window");
+                    } else {
+                        // stays workspace_hidden until the map notify comes back, so the transition keeps drawing it until then
+                        // (mapping it again in the meantime doesn't do anything)
+                        unsafe {
+                            if shows_frame(&w) {
+                                XMapWindow(display, w.frame_id);
+                            }
+                            XMapWindow(display, w.window_id);
+                        }
+                    }
+                }
                 if windows_to_destroy.contains(&w.window_id) {
                     println!("completely destroying window");
                     windows.remove_at_index(i).expect("Error removing window");
+                    need_retile = true;
+                    // keep what we need to play the closing animation after the window is gone
+                    let mut textures = window_textures.remove(&w.window_id).unwrap_or_default();
+                    let mesh = wobbly_meshes.remove(&w.window_id);
+                    let blur_region = blur_regions.remove(&w.window_id);
+                    snap_restore_sizes.remove(&w.window_id);
+                    saved_geometries.remove(&w.window_id);
+                    requested_positions.remove(&w.window_id);
+                    shading_unmaps.retain(|&x| x != w.window_id);
+                    client_list.retain(|&x| x != w.window_id);
+                    for window in workspace_focus.iter_mut().filter(|window| **window == w.window_id) {
+                        *window = 0;
+                    }
+                    publish_client_list(display, root, &client_list);
+                    let animation = match animations.remove(&w.window_id) {
+                        Some(animation) if animation.kind.is_closing() => Some(animation), // let the unmap/minimize finish
+                        _ if !w.hide => Some(Animation::new(AnimationKind::Destroy, current_transform.unwrap_or(Transform::IDENTITY),
+                                                            Transform::HIDDEN, config.animation_settings(AnimationKind::Destroy))),
+                        _ => None,
+                    };
+                    match animation {
+                        Some(animation) => closing_windows.push(ClosingWindow { window: w, mesh, textures, blur_region, animation }),
+                        None => textures.delete(),
+                    }
+                    unsafe {
+                        XDestroyWindow(display, w.frame_id);
+                    }
                     windows_to_destroy.retain(|&x| x != w.window_id);
                     el = windows.index(0);
                     i = 0;
@@ -639,7 +1877,7 @@ gl_FragColor = texture2D(tex, Texcoord);
 
                             unsafe {
                                 XMoveWindow(display, w.frame_id, w.x as i32 - 10, w.y as i32 - 20);
-                                XResizeWindow(display, w.frame_id, (w.width + 20) as c_uint, (w.height + 25) as c_uint);
+                                XResizeWindow(display, w.frame_id, (w.width + 20) as c_uint, w.frame_height() as c_uint);
                             }
 
                             let format = unsafe { XRenderFindVisualFormat(display, attr.visual) };
@@ -650,6 +1888,23 @@ gl_FragColor = texture2D(tex, Texcoord);
                             windows_to_configure.retain(|x| x != &w.window_id);
                         }
 
+                        // clicking anywhere on a window focuses it
+                        // (shaded clients aren't mapped, so there's nothing to focus)
+                        if (child_return == w.frame_id || child_return == w.window_id) && w.window_id != desktop_id
+                            && mask_return & Button1Mask as u32 != 0 && focused_window != w.window_id && !w.state.shaded
+                            && w.window_type.takes_focus() {
+                            focused_window = w.window_id;
+                            unsafe {
+                                XSetInputFocus(display, w.window_id, RevertToPointerRoot as c_int, CurrentTime as c_ulong);
+                            }
+                            // it's got the attention it wanted
+                            if w.state.demands_attention {
+                                w.state.demands_attention = false;
+                                publish_state(display, &w);
+                                windows.change_element_at_index(i, w).expect("Error changing window");
+                            }
+                        }
+
                         // did the window get picked up?
                         if (child_return == w.frame_id || root_return == w.frame_id) && w.window_id != desktop_id && mask_return & Button1Mask as u32 != 0 {
                             println!("picking up window");
@@ -657,14 +1912,40 @@ gl_FragColor = texture2D(tex, Texcoord);
                             w.hide = false;
                             w.use_actual_position = false;
                             holding_window = w.window_id;
+                            holding_transients = transients_of(&windows, w.window_id)
+                                .into_iter()
+                                .map(|j| unsafe { (*windows.index(j).unwrap()).value.window_id })
+                                .collect();
 
                             holding_window_x_offset = win_x_return as i32 - w.x;
                             holding_window_y_offset = win_y_return as i32 - w.y;
+                            // windows tiled into an edge or maximized go back to the size they had before
+                            let mut restore_size = snap_restore_sizes.remove(&w.window_id);
+                            if w.state.maximized() {
+                                w.state.maximized_vert = false;
+                                w.state.maximized_horz = false;
+                                restore_size = saved_geometries.remove(&w.window_id).map(|(_, _, width, height)| (width, height)).or(restore_size);
+                                publish_state(display, &w);
+                            }
+                            if let Some((width, height)) = restore_size {
+                                // keep the cursor over the same part of the title bar
+                                holding_window_x_offset = (holding_window_x_offset as f32 * width as f32 / w.width.max(1) as f32) as i32;
+                                w.width = width;
+                                w.height = height;
+                                unsafe {
+                                    XResizeWindow(display, w.window_id, width as c_uint, height as c_uint);
+                                }
+                            }
+                            if let Some(mesh) = wobbly_meshes.get_mut(&w.window_id) {
+                                mesh.grab(mouse_x as f32, mouse_y as f32);
+                            }
                             unsafe {
                                 XRaiseWindow(display, w.frame_id);
                                 XRaiseWindow(display, w.window_id);
                                 XFlush(display);
                             }
+                            // but not over anything that's kept above
+                            need_restack = true;
 
                             windows.change_element_at_index(i, w).expect("Error changing window");
                         }
@@ -677,43 +1958,134 @@ gl_FragColor = texture2D(tex, Texcoord);
                         if mask_return & Button1Mask as u32 == 0 {
                             // if so, move the window
                             println!("releasing window");
+                            if let Some(rect) = snap_preview.take() {
+                                snap_restore_sizes.entry(w.window_id).or_insert((w.width, w.height));
+                                holding_window_x = rect.x + 10;
+                                holding_window_y = rect.y + 20;
+                                w.width = (rect.width - 20).max(1) as u16;
+                                w.height = (rect.height - 25).max(1) as u16;
+                                unsafe {
+                                    XResizeWindow(display, w.window_id, w.width as c_uint, w.height as c_uint);
+                                }
+                            }
                             unsafe {
                                 XMoveWindow(display, w.window_id, holding_window_x as c_int, holding_window_y as c_int);
                                 XSync(display, 0);
                             }
+                            // anything that belongs to it goes wherever it got dropped too
+                            shift_windows(display, &mut windows, &holding_transients, holding_window_x - w.x, holding_window_y - w.y, true);
+                            holding_transients.clear();
                             w.use_actual_position = true;
                             w.x = holding_window_x;
                             w.y = holding_window_y;
+                            if let Some(mesh) = wobbly_meshes.get_mut(&w.window_id) {
+                                mesh.release();
+                            }
+                            // dragging a window out of its tile makes it float
+                            if is_tiled(&w) && layouts[workspace_layouts[current_workspace]].tiles() {
+                                w.floating = true;
+                                need_retile = true;
+                            }
                             windows.change_element_at_index(i, w).expect("Error changing window");
                             windows_to_finally_move.retain(|x| x != &w.window_id);
                             holding_window = 0;
                             dont_move = true;
                         }
                         if !dont_move {
+                            let (old_x, old_y) = (w.x, w.y);
                             // move the window to the cursor position (minus the offset)
                             w.x = mouse_x - holding_window_x_offset;
                             w.y = mouse_y - holding_window_y_offset;
+                            // edges to tile against and snap to are the ones of the monitor it's being dragged on
+                            let (_, usable_area) = monitor_at(&monitor_areas, mouse_x, mouse_y);
+                            snap_preview = if config.edge_tiling { snap::edge_tile(mouse_x, mouse_y, usable_area, &config.tiling) } else { None };
+                            // no point lining up with other windows if it's about to get tiled anyway
+                            if snap_preview.is_none() && config.snap_threshold > 0 {
+                                let frame = Rect { x: w.x - 10, y: w.y - 20, width: w.width as i32 + 20, height: w.height as i32 + 25 };
+                                let (x, y) = snap::snap(frame, &snap_targets, usable_area, config.snap_threshold);
+                                w.x = x + 10;
+                                w.y = y + 20;
+                            }
                             holding_window_x = w.x;
                             holding_window_y = w.y;
+                            // dialogs follow their parent around (x only hears about it when it's let go)
+                            if (w.x, w.y) != (old_x, old_y) {
+                                shift_windows(display, &mut windows, &holding_transients, w.x - old_x, w.y - old_y, false);
+                            }
                         }
-                        draw_x_window(w, true, display, shader_program,
-                                      false, 0, 0, r as u32, g as u32, b as u32);
-                    } else {
-                        // draw the window
-                        if !w.hide {
-                            if w.window_id != desktop_id {
-                                draw_x_window(w, true, display, shader_program,
-                                              false, 0, 0, r as u32, g as u32, b as u32);
+                    }
+
+                    // windows on the workspaces being switched between lag behind the slide a bit
+                    let layer = transitions.layer_for(w.workspace, current_workspace);
+                    let motion = transitions.motion();
+                    if motion != 0.0 && matches!(layer, Some(Layer::Outgoing) | Some(Layer::Incoming)) {
+                        if let Some(mesh) = wobbly_meshes.get_mut(&w.window_id) {
+                            mesh.shove(motion);
+                        }
+                    }
+
+                    // let the control points catch up with where the frame is now. fullscreen windows have no frame and don't wobble
+                    let fullscreen = w.state.fullscreen;
+                    let mesh = wobbly_meshes.get_mut(&w.window_id).filter(|_| !fullscreen).map(|mesh| {
+                        mesh.set_rect((w.x - 10) as f32, (w.y - 20) as f32, (w.width + 20) as f32, w.frame_height() as f32);
+                        mesh.advance(dt, config.wobbly_spring_k, config.wobbly_friction);
+                        &*mesh
+                    });
+
+                    // fade towards whatever opacity the window should have right now
+                    let mut wanted_opacity = w.rule.opacity.unwrap_or(w.net_wm_opacity);
+                    if focused_window != w.window_id {
+                        wanted_opacity *= w.rule.inactive_opacity.unwrap_or(config.inactive_opacity);
+                    }
+                    if w.opacity != wanted_opacity {
+                        // fade_step is per 60hz frame, unless it's 1.0 and fading is off
+                        let step = if config.fade_step >= 1.0 { 1.0 } else { config.fade_step * dt };
+                        if w.opacity < wanted_opacity {
+                            w.opacity = (w.opacity + step).min(wanted_opacity);
+                        } else {
+                            w.opacity = (w.opacity - step).max(wanted_opacity);
+                        }
+                        windows.change_element_at_index(i, w).expect("Error changing window");
+                    }
+
+                    let transform = animations.get(&w.window_id).map(|a| a.current()).unwrap_or(Transform::IDENTITY);
+                    let textures = window_textures.entry(w.window_id).or_default();
+                    let shadow = window_shadow(&w, &config, &mut shadow_cache);
+                    let window_blur = blur_behind(&w, &config, blur.as_ref(), blur_regions.get(&w.window_id));
+                    let corner_radius = window_corner_radius(&w, &config);
+                    let focused = focused_window == w.window_id;
+                    let shader = custom_shaders.program(window_shader(&w, &config, focused));
+                    let border = border_theme(&config, focused).border_fill(t, wallpaper_color, focused);
+                    // windows on other workspaces don't get drawn at all
+                    if let Some(layer) = layer {
+                        transitions.bind(&mut renderer, layer);
+                        if holding_window == w.window_id && !w.hide {
+                            draw_x_window(w, mesh, &mut renderer, textures, transform, shadow, window_blur, corner_radius, shader, focused, shows_frame(&w), display,
+                                          fullscreen, border);
+                        } else {
+                            // draw the window
+                            if !w.hide {
+                                if w.window_id != desktop_id {
+                                    draw_x_window(w, mesh, &mut renderer, textures, transform, shadow, window_blur, corner_radius, shader, focused, shows_frame(&w), display,
+                                                  fullscreen, border);
+                                }
+                            } else if animations.contains_key(&w.window_id) || (w.workspace_hidden && transitions.is_running()) {
+                                // it's unmapped already, so draw the last picture we got of it while it fades (or slides) out
+                                draw_window_textures(w, mesh, &mut renderer, textures, transform, shadow, window_blur, corner_radius, shader, focused, shows_frame(&w),
+                                                     fullscreen, border);
                             }
                         }
                     }
+                    if animations.get(&w.window_id).map_or(false, |a| a.is_finished()) {
+                        animations.remove(&w.window_id);
+                    }
 
                     if w.x != w.velocity.last_x_location { // todo: this needs to come before the window is drawn
                         w.velocity.x_speed -= (w.x - w.velocity.last_x_location) as f64 * 0.1;
                         w.velocity.last_x_location = w.x;
                         windows.change_element_at_index(i, w).expect("Error changing window");
                     } else if w.velocity.x_speed != 0.0 {
-                        w.velocity.x_speed *= 0.89;
+                        w.velocity.x_speed *= 0.89f64.powf(dt as f64);
                         windows.change_element_at_index(i, w).expect("Error changing window");
                     }
 
@@ -722,16 +2094,221 @@ gl_FragColor = texture2D(tex, Texcoord);
                 }
             }
 
+            transitions.end_frame(&mut renderer);
+
+            if need_restack {
+                need_restack = false;
+                restack(display, &mut windows, &unmanaged_windows, desktop_id);
+            }
+
+            // popups go over every window we manage, exactly where they put themselves
+            for w in unmanaged_windows.iter().filter(|w| !w.hide) {
+                let textures = window_textures.entry(w.window_id).or_default();
+                let window_blur = blur_behind(w, &config, blur.as_ref(), blur_regions.get(&w.window_id));
+                let border = border_theme(&config, false).border_fill(t, wallpaper_color, false);
+                draw_x_window(*w, None, &mut renderer, textures, Transform::IDENTITY, None, window_blur, 0.0, custom_shaders.program(None), false, false, display,
+                              false, border);
+            }
+
+            // where the held window is about to be tiled, before it's let go
+            if let Some(rect) = snap_preview {
+                snap::draw_preview(&mut renderer, rect, border_theme(&config, true).color_at(t, wallpaper_color, true), config.corner_radius);
+            }
+
+            // windows that are already gone go on top until their animation is over
+            for closing in closing_windows.iter_mut() {
+                let mesh = closing.mesh.as_mut().map(|mesh| {
+                    mesh.advance(dt, config.wobbly_spring_k, config.wobbly_friction);
+                    &*mesh
+                });
+                let shadow = window_shadow(&closing.window, &config, &mut shadow_cache);
+                let window_blur = blur_behind(&closing.window, &config, blur.as_ref(), closing.blur_region.as_ref());
+                let corner_radius = window_corner_radius(&closing.window, &config);
+                // a window that's going away can't have focus
+                let shader = custom_shaders.program(window_shader(&closing.window, &config, false));
+                let border = border_theme(&config, false).border_fill(t, wallpaper_color, false);
+                let fullscreen = closing.window.state.fullscreen;
+                draw_window_textures(closing.window, mesh, &mut renderer, &closing.textures, closing.animation.current(), shadow, window_blur, corner_radius, shader, false, shows_frame(&closing.window),
+                                     fullscreen, border);
+            }
+
             // we don't want to accidentally destroy a window, so clear the windows to destroy list
             windows_to_destroy.clear();
             // likewise, clear the windows to hide list
             windows_to_hide.clear();
+            windows_withdrawn.clear();
+            // and any opacity changes for windows we don't manage
+            windows_to_update_opacity.clear();
+            windows_to_update_blur.clear();
+            windows_to_update_bypass.clear();
+            windows_to_update_strut.clear();
+            windows_to_update_type.clear();
+            // anything still waiting to be opened or minimized isn't a window we manage, and would stop us ever unredirecting
+            windows_to_open.clear();
+            windows_to_minimize.clear();
+            windows_to_move_workspace.clear();
+            windows_to_toggle_floating.clear();
+            windows_to_change_state.clear();
+
+
+            renderer.end_frame();
+            // textures drawn this frame are only done with once it's been flushed, so finished closing windows
+            // and shadows that fell out of the cache get deleted now
+            closing_windows.retain_mut(|closing| {
+                if closing.animation.is_finished() {
+                    closing.textures.delete();
+                    return false;
+                }
+                true
+            });
+            shadow_cache.delete_evicted();
+            frame_clock.swap();
+            need_redraw = false;
+        } else if events_pending == 0 {
+            // nothing to draw yet and nothing to handle, so don't spin
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libsex::bindings::StaticGravity;
 
-            unsafe {
-                glXSwapBuffers(display, overlay_window);
-            }
-            need_redraw = false;
+    const SCREEN: Rect = Rect { x: 0, y: 0, width: 1920, height: 1080 };
+    // two monitors side by side: a big one on the left and a smaller one to the right of it, lined up at the top
+    const DUAL: Rect = Rect { x: 0, y: 0, width: 3200, height: 1080 };
+    const LEFT: Rect = Rect { x: 0, y: 0, width: 1920, height: 1080 };
+    const RIGHT: Rect = Rect { x: 1920, y: 0, width: 1280, height: 720 };
+
+    fn window(x: i32, y: i32, width: u16, height: u16, window_type: WindowType, strut: Option<[u32; 12]>) -> CumWindow {
+        CumWindow { x, y, width, height, window_type, strut, ..CumWindow::default() }
+    }
+
+    fn list(windows: &[CumWindow]) -> LinkedList {
+        let mut list = LinkedList::new();
+        for &w in windows {
+            list.push(w).unwrap();
+        }
+        list
+    }
+
+    #[test]
+    fn gravity_moves_the_client_inside_its_frame() {
+        let cases = [
+            (NorthWestGravity, (110, 220)),
+            (NorthGravity, (100, 220)),
+            (NorthEastGravity, (90, 220)),
+            (WestGravity, (110, 207)),
+            (CenterGravity, (100, 207)),
+            (EastGravity, (90, 207)),
+            (SouthWestGravity, (110, 195)),
+            (SouthGravity, (100, 195)),
+            (SouthEastGravity, (90, 195)),
+            (StaticGravity, (100, 200)),
+        ];
+        for (gravity, expected) in cases {
+            assert_eq!(apply_gravity(gravity as c_int, FRAME_EXTENTS, 100, 200), expected, "gravity {}", gravity);
+            // without a frame there's nothing to make room for
+            assert_eq!(apply_gravity(gravity as c_int, [0; 4], 100, 200), (100, 200), "gravity {}", gravity);
         }
     }
+
+    #[test]
+    fn only_windows_showing_a_frame_have_extents() {
+        let decorated = CumWindow { decorated: true, ..CumWindow::default() };
+        assert_eq!(frame_extents(&decorated), FRAME_EXTENTS);
+        let mut fullscreen = decorated;
+        fullscreen.state.fullscreen = true;
+        assert_eq!(frame_extents(&fullscreen), [0; 4]);
+        assert_eq!(frame_extents(&CumWindow { decorated: false, ..CumWindow::default() }), [0; 4]);
+    }
+
+    #[test]
+    fn frame_extents_requests_are_answered_from_the_window_type() {
+        for window_type in [WindowType::Normal, WindowType::Dialog, WindowType::Utility, WindowType::Toolbar] {
+            assert_eq!(expected_frame_extents(window_type), FRAME_EXTENTS, "{:?}", window_type);
+        }
+        for window_type in [WindowType::Dock, WindowType::Desktop, WindowType::Menu, WindowType::Tooltip, WindowType::Notification] {
+            assert_eq!(expected_frame_extents(window_type), [0; 4], "{:?}", window_type);
+        }
+    }
+
+    #[test]
+    fn no_panels_leaves_the_whole_screen() {
+        let windows = list(&[window(100, 100, 800, 600, WindowType::Normal, None)]);
+        assert_eq!(work_area(&windows, SCREEN, SCREEN), SCREEN);
+    }
+
+    #[test]
+    fn struts_take_space_from_their_edges() {
+        let windows = list(&[
+            window(0, 0, 1920, 30, WindowType::Dock, Some([0, 0, 30, 0, 0, 0, 0, 0, 0, 1919, 0, 0])),
+            window(1870, 30, 50, 1050, WindowType::Dock, Some([0, 50, 0, 0, 0, 0, 30, 1079, 0, 0, 0, 0])),
+        ]);
+        assert_eq!(work_area(&windows, SCREEN, SCREEN), Rect::new(0, 30, 1870, 1050));
+    }
+
+    #[test]
+    fn hidden_panels_dont_take_space() {
+        let mut panel = window(0, 0, 1920, 30, WindowType::Dock, Some([0, 0, 30, 0, 0, 0, 0, 0, 0, 1919, 0, 0]));
+        panel.hide = true;
+        assert_eq!(work_area(&list(&[panel]), SCREEN, SCREEN), SCREEN);
+    }
+
+    #[test]
+    fn docks_without_struts_keep_the_edge_they_sit_on() {
+        let windows = list(&[
+            window(0, 1056, 1920, 24, WindowType::Dock, None),
+            window(0, 0, 48, 1056, WindowType::Dock, None),
+        ]);
+        assert_eq!(work_area(&windows, SCREEN, SCREEN), Rect::new(48, 0, 1872, 1056));
+
+        // the same bar as a normal window is just a window
+        let windows = list(&[window(0, 1056, 1920, 24, WindowType::Normal, None)]);
+        assert_eq!(work_area(&windows, SCREEN, SCREEN), SCREEN);
+    }
+
+    #[test]
+    fn partial_struts_only_take_space_from_monitors_they_reach() {
+        // a bar along the top of the right monitor only
+        let windows = list(&[window(1920, 0, 1280, 30, WindowType::Dock, Some([0, 0, 30, 0, 0, 0, 0, 0, 1920, 3199, 0, 0]))]);
+        assert_eq!(work_area(&windows, LEFT, DUAL), LEFT);
+        assert_eq!(work_area(&windows, RIGHT, DUAL), Rect::new(1920, 30, 1280, 690));
+
+        // a bar along the bottom of the left monitor, ending right where the other monitor starts
+        let windows = list(&[window(0, 1040, 1920, 40, WindowType::Dock, Some([0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 1919]))]);
+        assert_eq!(work_area(&windows, LEFT, DUAL), Rect::new(0, 0, 1920, 1040));
+        assert_eq!(work_area(&windows, RIGHT, DUAL), RIGHT);
+    }
+
+    #[test]
+    fn struts_count_from_the_edge_of_the_screen() {
+        // the right monitor is shorter than the screen, so a 30 pixel bar at its bottom needs everything under it too
+        let windows = list(&[window(1920, 690, 1280, 30, WindowType::Dock, Some([0, 0, 0, 390, 0, 0, 0, 0, 0, 0, 1920, 3199]))]);
+        assert_eq!(work_area(&windows, RIGHT, DUAL), Rect::new(1920, 0, 1280, 690));
+        assert_eq!(work_area(&windows, LEFT, DUAL), LEFT);
+
+        // the right edge of the screen is the right edge of the right monitor, whatever the left one's height
+        let windows = list(&[window(3150, 0, 50, 720, WindowType::Dock, Some([0, 50, 0, 0, 0, 0, 0, 719, 0, 0, 0, 0]))]);
+        assert_eq!(work_area(&windows, RIGHT, DUAL), Rect::new(1920, 0, 1230, 720));
+        assert_eq!(work_area(&windows, LEFT, DUAL), LEFT);
+    }
+
+    #[test]
+    fn docks_without_struts_only_take_space_from_their_monitor() {
+        let windows = list(&[window(1920, 0, 1280, 24, WindowType::Dock, None)]);
+        assert_eq!(work_area(&windows, RIGHT, DUAL), Rect::new(1920, 24, 1280, 696));
+        assert_eq!(work_area(&windows, LEFT, DUAL), LEFT);
+    }
+
+    #[test]
+    fn anywhere_off_every_monitor_is_on_the_first_one() {
+        let areas = [(LEFT, LEFT), (RIGHT, RIGHT)];
+        assert_eq!(monitor_at(&areas, 100, 100), (LEFT, LEFT));
+        assert_eq!(monitor_at(&areas, 2000, 100), (RIGHT, RIGHT));
+        // under the shorter monitor
+        assert_eq!(monitor_at(&areas, 2000, 900), (LEFT, LEFT));
+    }
 }
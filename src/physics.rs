@@ -0,0 +1,178 @@
+// wobbly windows: each window is a grid of control points held together by springs
+
+#[derive(Clone, Copy)]
+pub struct MeshPoint {
+    pub x: f32, // where the point currently is
+    pub y: f32, // where the point currently is
+    x_speed: f32,
+    y_speed: f32,
+}
+
+pub struct WobblyMesh {
+    pub cols: usize, // amount of control points across
+    pub rows: usize, // amount of control points down
+    pub points: Vec<MeshPoint>, // row by row, top left first
+    rect: (f32, f32, f32, f32), // x, y, width, height of where the mesh wants to be when it's resting
+    grabbed: Option<usize>, // index of the point that's pinned to the cursor
+}
+
+impl WobblyMesh {
+    pub fn new(x: f32, y: f32, width: f32, height: f32, cols: usize, rows: usize) -> Self {
+        let mut mesh = WobblyMesh {
+            cols: cols.max(2),
+            rows: rows.max(2),
+            points: Vec::new(),
+            rect: (x, y, width, height),
+            grabbed: None,
+        };
+        for i in 0..mesh.cols * mesh.rows {
+            let (x, y) = mesh.rest_position(i);
+            mesh.points.push(MeshPoint { x, y, x_speed: 0.0, y_speed: 0.0 });
+        }
+        mesh
+    }
+
+    // where point i would be if nothing was wobbling
+    fn rest_position(&self, i: usize) -> (f32, f32) {
+        let col = (i % self.cols) as f32 / (self.cols - 1) as f32;
+        let row = (i / self.cols) as f32 / (self.rows - 1) as f32;
+        (self.rect.0 + col * self.rect.2, self.rect.1 + row * self.rect.3)
+    }
+
+    // move the resting rectangle; the points will spring towards it on the next steps
+    pub fn set_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        self.rect = (x, y, width, height);
+    }
+
+    // pin the control point closest to the cursor
+    pub fn grab(&mut self, cursor_x: f32, cursor_y: f32) {
+        let mut closest = 0;
+        let mut closest_distance = f32::MAX;
+        for (i, point) in self.points.iter().enumerate() {
+            let distance = (point.x - cursor_x).powi(2) + (point.y - cursor_y).powi(2);
+            if distance < closest_distance {
+                closest = i;
+                closest_distance = distance;
+            }
+        }
+        self.grabbed = Some(closest);
+    }
+
+    pub fn release(&mut self) {
+        self.grabbed = None;
+    }
+
+    pub fn is_grabbed(&self) -> bool {
+        self.grabbed.is_some()
+    }
+
+    // the window got carried sideways by dx pixels without its resting place moving (like when workspaces slide),
+    // so the side facing away from the movement gets left behind the most
+    pub fn shove(&mut self, dx: f32) {
+        for i in 0..self.points.len() {
+            let col = (i % self.cols) as f32 / (self.cols - 1) as f32;
+            let trailing = if dx < 0.0 { col } else { 1.0 - col };
+            self.points[i].x_speed -= dx * 0.1 * trailing;
+        }
+    }
+
+    // advance the simulation; dt is measured in frames (1.0 = one 60hz frame)
+    pub fn step(&mut self, dt: f32, spring_k: f32, friction: f32) {
+        let mut forces = vec![(0.0f32, 0.0f32); self.points.len()];
+        for i in 0..self.points.len() {
+            let (rest_x, rest_y) = self.rest_position(i);
+            let col = i % self.cols;
+            let row = i / self.cols;
+            // springs to the right and below, applied to both ends
+            let mut neighbours = Vec::with_capacity(2);
+            if col + 1 < self.cols {
+                neighbours.push(i + 1);
+            }
+            if row + 1 < self.rows {
+                neighbours.push(i + self.cols);
+            }
+            for j in neighbours {
+                let (other_rest_x, other_rest_y) = self.rest_position(j);
+                // the spring wants to keep the same offset between the points as when resting
+                let stretch_x = (self.points[j].x - self.points[i].x) - (other_rest_x - rest_x);
+                let stretch_y = (self.points[j].y - self.points[i].y) - (other_rest_y - rest_y);
+                forces[i].0 += stretch_x * spring_k;
+                forces[i].1 += stretch_y * spring_k;
+                forces[j].0 -= stretch_x * spring_k;
+                forces[j].1 -= stretch_y * spring_k;
+            }
+            // once let go, every point gets pulled back to where it belongs
+            if self.grabbed.is_none() {
+                forces[i].0 += (rest_x - self.points[i].x) * spring_k * 0.5;
+                forces[i].1 += (rest_y - self.points[i].y) * spring_k * 0.5;
+            }
+        }
+
+        for i in 0..self.points.len() {
+            if self.grabbed == Some(i) {
+                // the grabbed point goes wherever the window goes
+                let (rest_x, rest_y) = self.rest_position(i);
+                self.points[i] = MeshPoint { x: rest_x, y: rest_y, x_speed: 0.0, y_speed: 0.0 };
+                continue;
+            }
+            let point = &mut self.points[i];
+            point.x_speed = (point.x_speed + forces[i].0 * dt) * (1.0 - friction).powf(dt);
+            point.y_speed = (point.y_speed + forces[i].1 * dt) * (1.0 - friction).powf(dt);
+            point.x += point.x_speed * dt;
+            point.y += point.y_speed * dt;
+        }
+
+        // snap to rest once the wobbling is too small to see, so idle windows stay sharp
+        if self.grabbed.is_none() && self.is_settled() {
+            for i in 0..self.points.len() {
+                let (x, y) = self.rest_position(i);
+                self.points[i] = MeshPoint { x, y, x_speed: 0.0, y_speed: 0.0 };
+            }
+        }
+    }
+
+    // advance by however many frames went by, at most one at a time so a slow frame can't make the springs blow up
+    pub fn advance(&mut self, dt: f32, spring_k: f32, friction: f32) {
+        let mut remaining = dt;
+        while remaining > 0.0 {
+            let step = remaining.min(1.0);
+            self.step(step, spring_k, friction);
+            remaining -= step;
+        }
+    }
+
+    // how fast the window is moving sideways, in pixels per frame: the average speed of all the control points
+    pub fn x_velocity(&self) -> f32 {
+        self.points.iter().map(|point| point.x_speed).sum::<f32>() / self.points.len() as f32
+    }
+
+    pub fn is_settled(&self) -> bool {
+        self.points.iter().enumerate().all(|(i, point)| {
+            let (rest_x, rest_y) = self.rest_position(i);
+            (point.x - rest_x).abs() < 0.5 && (point.y - rest_y).abs() < 0.5
+                && point.x_speed.abs() < 0.1 && point.y_speed.abs() < 0.1
+        })
+    }
+
+    // where a spot on the window ends up after deforming; u and v go from 0.0 to 1.0 across the resting rectangle,
+    // anything outside that (like a shadow) follows the nearest edge cells
+    pub fn position_at(&self, u: f32, v: f32) -> (f32, f32) {
+        let fx = u * (self.cols - 1) as f32;
+        let fy = v * (self.rows - 1) as f32;
+        let col = (fx as usize).min(self.cols - 2);
+        let row = (fy as usize).min(self.rows - 2);
+        let tx = fx - col as f32;
+        let ty = fy - row as f32;
+
+        let top_left = self.points[row * self.cols + col];
+        let top_right = self.points[row * self.cols + col + 1];
+        let bottom_left = self.points[(row + 1) * self.cols + col];
+        let bottom_right = self.points[(row + 1) * self.cols + col + 1];
+
+        let top_x = top_left.x + (top_right.x - top_left.x) * tx;
+        let top_y = top_left.y + (top_right.y - top_left.y) * tx;
+        let bottom_x = bottom_left.x + (bottom_right.x - bottom_left.x) * tx;
+        let bottom_y = bottom_left.y + (bottom_right.y - bottom_left.y) * tx;
+        (top_x + (bottom_x - top_x) * ty, top_y + (bottom_y - top_y) * ty)
+    }
+}
@@ -0,0 +1,50 @@
+// `cwm --layout <name>` and friends: instead of starting another cwm, send the one that's already running a client message
+use std::mem;
+use std::os::raw::{c_int, c_long};
+use libsex::bindings::{ClientMessage, Display, SubstructureNotifyMask, SubstructureRedirectMask, Window, XEvent, XGetInputFocus,
+                       XSendEvent, XSync};
+use crate::helpers::get_atom;
+use crate::layout;
+
+fn send(display: *mut Display, root: Window, window: Window, message: &str, value: c_long) {
+    unsafe {
+        let mut event: XEvent = mem::zeroed();
+        event.xclient.type_ = ClientMessage as c_int;
+        event.xclient.send_event = 1;
+        event.xclient.display = display;
+        event.xclient.window = window;
+        event.xclient.message_type = get_atom(display, message);
+        event.xclient.format = 32;
+        event.xclient.data.l[0] = value;
+        // the same way pagers talk to window managers
+        XSendEvent(display, root, 0, (SubstructureNotifyMask | SubstructureRedirectMask) as c_long, &mut event);
+        XSync(display, 0);
+    }
+}
+
+// returns true if args were a command for the running cwm, which means this one has nothing else to do
+pub fn run(display: *mut Display, root: Window, args: &[String]) -> bool {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("--layout") => {
+            let name = args.get(1).map_or("next", |name| name.as_str());
+            if name != "next" && layout::index_of(name).is_none() {
+                println!("unknown layout {} (expected floating, master-stack, columns, monocle, bsp or next)", name);
+                return true;
+            }
+            // the layout goes over as the atom for its name, 0 means the next one
+            let layout = if name == "next" { 0 } else { get_atom(display, name) as c_long };
+            send(display, root, root, "_CWM_LAYOUT", layout);
+        }
+        Some("--toggle-floating") => {
+            // whatever has focus, which cwm sets to the client window
+            let mut focus: Window = 0;
+            let mut revert_to: c_int = 0;
+            unsafe {
+                XGetInputFocus(display, &mut focus, &mut revert_to);
+            }
+            send(display, root, focus, "_CWM_TOGGLE_FLOATING", 0);
+        }
+        _ => return false,
+    }
+    true
+}
@@ -0,0 +1,512 @@
+use std::ffi::{c_void, CString};
+use std::mem;
+use std::ptr::null;
+use std::time::Instant;
+use libsex::bindings::*;
+
+// floats per vertex: x, y, then s, t for the texture, then u, v for where the vertex sits in the
+// rectangle being drawn (0.0 - 1.0), which the shader uses to round the corners
+pub const VERTEX_SIZE: usize = 6;
+
+// every program gets its attributes bound to these before linking, so one buffer layout works for all of them
+const POSITION_ATTRIB: GLuint = 0;
+const TEXCOORD_ATTRIB: GLuint = 1;
+const LOCAL_ATTRIB: GLuint = 2;
+
+pub const VERTEX_SHADER: &str = "
+#version 120
+attribute vec2 position;
+attribute vec2 texcoord;
+attribute vec2 local; // where we are in the rectangle being drawn, 0.0 - 1.0
+uniform mat4 projection;
+varying vec2 Texcoord;
+varying vec2 Local;
+
+void main()
+{
+gl_Position = projection * vec4(position, 0.0, 1.0);
+Texcoord = texcoord;
+Local = local;
+}
+";
+
+const FRAGMENT_SHADER: &str = "
+#version 120
+varying vec2 Texcoord;
+varying vec2 Local;
+
+uniform sampler2D tex;
+uniform int kind; // 0 = texture, 1 = premultiplied texture, 2 = alpha mask tinted with color, 3 = solid color, 4 = gradient
+uniform vec4 color;
+uniform vec4 color2; // where gradients end up, at the bottom right
+uniform float opacity;
+uniform vec2 size; // size of the rectangle being drawn, in pixels
+uniform float radius; // corner radius in pixels, 0.0 for square corners
+
+// 1.0 inside the rounded rectangle, 0.0 outside, antialiased over a pixel at the edge
+float rounded_mask(vec2 position)
+{
+if (radius <= 0.0) {
+return 1.0;
+}
+vec2 q = abs(position - size * 0.5) - (size * 0.5 - vec2(radius));
+float distance = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - radius;
+return clamp(0.5 - distance, 0.0, 1.0);
+}
+
+void main()
+{
+vec4 texel = texture2D(tex, Texcoord);
+vec4 result;
+if (kind == 0) {
+result = vec4(texel.rgb * texel.a, texel.a);
+} else if (kind == 1) {
+result = texel;
+} else if (kind == 2) {
+result = vec4(color.rgb, 1.0) * texel.a * color.a;
+} else if (kind == 3) {
+result = vec4(color.rgb * color.a, color.a);
+} else {
+vec4 mixed = mix(color, color2, clamp((Local.x + Local.y) * 0.5, 0.0, 1.0));
+result = vec4(mixed.rgb * mixed.a, mixed.a);
+}
+// everything comes out premultiplied, so one blend function works for all of it
+gl_FragColor = result * opacity * rounded_mask(Local * size);
+}
+";
+
+// what to fill the triangles (or lines) with
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fill {
+    Texture(GLuint), // normal texture, or one without alpha
+    Premultiplied(GLuint), // argb windows come from x with their colours already multiplied by alpha
+    Mask(GLuint, [f32; 4]), // alpha-only texture (like a shadow) tinted with a colour
+    Solid([f32; 4]),
+    Gradient([f32; 4], [f32; 4]), // from the top left of the rectangle to the bottom right
+}
+
+// everything the shader needs for one draw; consecutive draws with the same params get batched together
+#[derive(Clone, Copy, PartialEq)]
+pub struct DrawParams {
+    pub fill: Fill,
+    pub opacity: f32,
+    pub size: (f32, f32), // size of the rectangle in pixels, for the corners
+    pub corner_radius: f32,
+    pub shader: GLuint, // a custom shader program, 0 for the default one
+    pub focused: bool, // only custom shaders care about these two
+    pub velocity: f32,
+}
+
+impl DrawParams {
+    pub fn new(fill: Fill, opacity: f32, size: (f32, f32), corner_radius: f32) -> Self {
+        DrawParams { fill, opacity, size, corner_radius, shader: 0, focused: false, velocity: 0.0 }
+    }
+}
+
+struct Batch {
+    params: DrawParams,
+    mode: GLenum,
+    first_index: usize,
+    count: usize,
+}
+
+// uniform locations, -1 for anything the program doesn't use (gl ignores those)
+struct Uniforms {
+    projection: GLint,
+    tex: GLint,
+    kind: GLint,
+    color: GLint,
+    color2: GLint,
+    opacity: GLint,
+    size: GLint,
+    radius: GLint,
+    time: GLint,
+    focused: GLint,
+    velocity: GLint,
+}
+
+impl Uniforms {
+    fn of(program: GLuint) -> Self {
+        Uniforms {
+            projection: uniform_location(program, b"projection\0"),
+            tex: uniform_location(program, b"tex\0"),
+            kind: uniform_location(program, b"kind\0"),
+            color: uniform_location(program, b"color\0"),
+            color2: uniform_location(program, b"color2\0"),
+            opacity: uniform_location(program, b"opacity\0"),
+            size: uniform_location(program, b"size\0"),
+            radius: uniform_location(program, b"radius\0"),
+            time: uniform_location(program, b"time\0"),
+            focused: uniform_location(program, b"focused\0"),
+            velocity: uniform_location(program, b"velocity\0"),
+        }
+    }
+}
+
+// a screen sized texture that can be drawn into instead of the screen; what ends up in it is premultiplied,
+// and its origin is at the bottom left like every other framebuffer
+pub struct RenderTarget {
+    framebuffer: GLuint,
+    pub texture: GLuint,
+}
+
+pub struct Renderer {
+    program: GLuint,
+    uniforms: Uniforms,
+    vbo: GLuint,
+    ibo: GLuint,
+    projection: [f32; 16],
+    width: i32,
+    height: i32,
+    start: Instant, // for the time uniform
+    post_process: Option<RenderTarget>, // the whole screen gets drawn in here first when there's a post-process shader
+    post_process_shader: GLuint, // for the frame being drawn right now, 0 for none
+    // queued up until the next flush
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    batches: Vec<Batch>,
+}
+
+impl Renderer {
+    pub fn new(width: i32, height: i32) -> Result<Renderer, String> {
+        let program = compile_shader_program(VERTEX_SHADER, FRAGMENT_SHADER)?;
+        let mut renderer = Renderer {
+            program,
+            uniforms: Uniforms::of(program),
+            vbo: 0,
+            ibo: 0,
+            // top left corner is the origin, like x
+            projection: ortho(0.0, width as f32, height as f32, 0.0),
+            width,
+            height,
+            start: Instant::now(),
+            post_process: None,
+            post_process_shader: 0,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            batches: Vec::new(),
+        };
+        unsafe {
+            glGenBuffers(1, &mut renderer.vbo);
+            glGenBuffers(1, &mut renderer.ibo);
+            glViewport(0, 0, width, height);
+            glEnable(GL_BLEND);
+            glBlendFunc(GL_ONE, GL_ONE_MINUS_SRC_ALPHA);
+        }
+        Ok(renderer)
+    }
+
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    // for when the screen changes size; the post-process target gets made again at the new size on the next frame
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.projection = ortho(0.0, width as f32, height as f32, 0.0);
+        if let Some(post_process) = self.post_process.take() {
+            post_process.delete();
+        }
+        unsafe {
+            glViewport(0, 0, width, height);
+        }
+    }
+
+    // call before drawing anything for a frame; with a post-process shader everything goes offscreen until end_frame
+    pub fn begin_frame(&mut self, post_process_shader: GLuint) {
+        self.post_process_shader = 0;
+        if post_process_shader != 0 && self.post_process.is_none() {
+            match RenderTarget::new(self.width, self.height) {
+                Ok(post_process) => self.post_process = Some(post_process),
+                Err(e) => println!("could not set up post-processing: {}", e),
+            }
+        }
+        if let Some(post_process) = &self.post_process {
+            if post_process_shader != 0 {
+                self.post_process_shader = post_process_shader;
+                unsafe {
+                    glBindFramebuffer(GL_FRAMEBUFFER, post_process.framebuffer);
+                }
+            }
+        }
+    }
+
+    // where the frame is going: the post-process target if there's a post-process shader, otherwise the screen
+    fn frame_framebuffer(&self) -> GLuint {
+        match &self.post_process {
+            Some(post_process) if self.post_process_shader != 0 => post_process.framebuffer,
+            _ => 0,
+        }
+    }
+
+    // draws everything queued so far, then sends whatever comes next into target, or back to the frame for None
+    pub fn bind_target(&mut self, target: Option<&RenderTarget>) {
+        self.flush();
+        let framebuffer = target.map_or(self.frame_framebuffer(), |target| target.framebuffer);
+        unsafe {
+            glBindFramebuffer(GL_FRAMEBUFFER, framebuffer);
+        }
+    }
+
+    // draws whatever is left, then runs the post-process shader over the whole screen if there is one
+    pub fn end_frame(&mut self) {
+        self.flush();
+        let post_process = match &self.post_process {
+            Some(post_process) if self.post_process_shader != 0 => post_process,
+            _ => return,
+        };
+        let program = self.post_process_shader;
+        let (width, height) = (self.width as f32, self.height as f32);
+        // the offscreen texture has its origin at the bottom left
+        let vertices = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+            width, 0.0, 1.0, 1.0, 1.0, 0.0,
+            0.0, height, 0.0, 0.0, 0.0, 1.0,
+            width, height, 1.0, 0.0, 1.0, 1.0,
+        ];
+        unsafe {
+            glBindFramebuffer(GL_FRAMEBUFFER, 0);
+            glDisable(GL_BLEND);
+            glActiveTexture(GL_TEXTURE0);
+            glBindTexture(GL_TEXTURE_2D, post_process.texture);
+            glUseProgram(program);
+            let uniforms = Uniforms::of(program);
+            glUniform1i(uniforms.tex, 0);
+            glUniform1i(uniforms.kind, 1);
+            glUniform1f(uniforms.opacity, 1.0);
+            glUniform2f(uniforms.size, width, height);
+            glUniform1f(uniforms.time, self.start.elapsed().as_secs_f32());
+        }
+        let projection = self.projection;
+        self.draw_with_program(program, &projection, &vertices, &[0, 1, 2, 1, 3, 2]);
+        unsafe {
+            glEnable(GL_BLEND);
+        }
+    }
+
+    // queues triangles (or lines) using the vertex layout from VERTEX_SIZE; nothing is drawn until flush
+    pub fn queue(&mut self, params: DrawParams, mode: GLenum, vertices: &[f32], indices: &[u32]) {
+        let base = (self.vertices.len() / VERTEX_SIZE) as u32;
+        let first_index = self.indices.len();
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.iter().map(|index| index + base));
+
+        if let Some(last) = self.batches.last_mut() {
+            if last.params == params && last.mode == mode {
+                last.count += indices.len();
+                return;
+            }
+        }
+        self.batches.push(Batch { params, mode, first_index, count: indices.len() });
+    }
+
+    // draws everything queued since the last flush, in order
+    pub fn flush(&mut self) {
+        if self.batches.is_empty() {
+            return;
+        }
+        let time = self.start.elapsed().as_secs_f32();
+        unsafe {
+            glEnable(GL_BLEND);
+            glBlendFunc(GL_ONE, GL_ONE_MINUS_SRC_ALPHA);
+            glActiveTexture(GL_TEXTURE0);
+            upload_vertices(self.vbo, self.ibo, &self.vertices, &self.indices);
+
+            // custom shaders can get reloaded (and their names reused) at any time, so only the default one's uniforms are kept
+            let mut current_program = 0;
+            let mut custom_uniforms = None;
+            for batch in &self.batches {
+                let params = batch.params;
+                let program = if params.shader != 0 { params.shader } else { self.program };
+                if program != current_program {
+                    current_program = program;
+                    glUseProgram(program);
+                    custom_uniforms = if program == self.program { None } else { Some(Uniforms::of(program)) };
+                    let uniforms = custom_uniforms.as_ref().unwrap_or(&self.uniforms);
+                    glUniformMatrix4fv(uniforms.projection, 1, GL_FALSE as GLboolean, self.projection.as_ptr());
+                    glUniform1i(uniforms.tex, 0);
+                    glUniform1f(uniforms.time, time);
+                }
+                let uniforms = custom_uniforms.as_ref().unwrap_or(&self.uniforms);
+
+                let (kind, texture, color, color2) = match params.fill {
+                    Fill::Texture(texture) => (0, texture, [1.0; 4], [1.0; 4]),
+                    Fill::Premultiplied(texture) => (1, texture, [1.0; 4], [1.0; 4]),
+                    Fill::Mask(texture, color) => (2, texture, color, color),
+                    Fill::Solid(color) => (3, 0, color, color),
+                    Fill::Gradient(from, to) => (4, 0, from, to),
+                };
+                glBindTexture(GL_TEXTURE_2D, texture);
+                glUniform1i(uniforms.kind, kind);
+                glUniform4f(uniforms.color, color[0], color[1], color[2], color[3]);
+                glUniform4f(uniforms.color2, color2[0], color2[1], color2[2], color2[3]);
+                glUniform1f(uniforms.opacity, params.opacity);
+                glUniform2f(uniforms.size, params.size.0, params.size.1);
+                glUniform1f(uniforms.radius, params.corner_radius);
+                glUniform1i(uniforms.focused, params.focused as GLint);
+                glUniform1f(uniforms.velocity, params.velocity);
+                glDrawElements(batch.mode, batch.count as GLsizei, GL_UNSIGNED_INT,
+                               (batch.first_index * mem::size_of::<u32>()) as *const c_void);
+            }
+
+            disable_vertex_attribs();
+        }
+        self.vertices.clear();
+        self.indices.clear();
+        self.batches.clear();
+    }
+
+    // draws straight away with another program (one that uses the same attributes), for things like the blur passes;
+    // anything still queued should be flushed first
+    pub fn draw_with_program(&mut self, program: GLuint, projection: &[f32; 16], vertices: &[f32], indices: &[u32]) {
+        unsafe {
+            glUseProgram(program);
+            glUniformMatrix4fv(uniform_location(program, b"projection\0"), 1, GL_FALSE as GLboolean, projection.as_ptr());
+            upload_vertices(self.vbo, self.ibo, vertices, indices);
+            glDrawElements(GL_TRIANGLES, indices.len() as GLsizei, GL_UNSIGNED_INT, null());
+            disable_vertex_attribs();
+        }
+    }
+}
+
+impl RenderTarget {
+    pub fn new(width: i32, height: i32) -> Result<RenderTarget, String> {
+        let mut target = RenderTarget { framebuffer: 0, texture: 0 };
+        unsafe {
+            glGenTextures(1, &mut target.texture);
+            glBindTexture(GL_TEXTURE_2D, target.texture);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+            glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+            glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA8 as GLint, width, height, 0, GL_RGBA, GL_UNSIGNED_BYTE, null::<c_void>());
+
+            glGenFramebuffers(1, &mut target.framebuffer);
+            glBindFramebuffer(GL_FRAMEBUFFER, target.framebuffer);
+            glFramebufferTexture2D(GL_FRAMEBUFFER, GL_COLOR_ATTACHMENT0, GL_TEXTURE_2D, target.texture, 0);
+            let complete = glCheckFramebufferStatus(GL_FRAMEBUFFER) == GL_FRAMEBUFFER_COMPLETE;
+            glBindFramebuffer(GL_FRAMEBUFFER, 0);
+            if !complete {
+                glDeleteFramebuffers(1, &target.framebuffer);
+                glDeleteTextures(1, &target.texture);
+                return Err("offscreen framebuffer is incomplete".to_string());
+            }
+        }
+        Ok(target)
+    }
+
+    pub fn delete(self) {
+        unsafe {
+            glDeleteFramebuffers(1, &self.framebuffer);
+            glDeleteTextures(1, &self.texture);
+        }
+    }
+}
+
+// column major, like gl wants it
+pub fn ortho(left: f32, right: f32, bottom: f32, top: f32) -> [f32; 16] {
+    [
+        2.0 / (right - left), 0.0, 0.0, 0.0,
+        0.0, 2.0 / (top - bottom), 0.0, 0.0,
+        0.0, 0.0, -1.0, 0.0,
+        -(right + left) / (right - left), -(top + bottom) / (top - bottom), 0.0, 1.0,
+    ]
+}
+
+pub fn uniform_location(program: GLuint, name: &[u8]) -> GLint {
+    unsafe { glGetUniformLocation(program, name.as_ptr() as *const GLchar) }
+}
+
+// streams the vertices into the buffers and points the attributes at them
+unsafe fn upload_vertices(vbo: GLuint, ibo: GLuint, vertices: &[f32], indices: &[u32]) {
+    let stride = (mem::size_of::<f32>() * VERTEX_SIZE) as GLsizei;
+    glBindBuffer(GL_ARRAY_BUFFER, vbo);
+    glBufferData(GL_ARRAY_BUFFER, (mem::size_of::<f32>() * vertices.len()) as GLsizeiptr, vertices.as_ptr() as *const c_void, GL_STREAM_DRAW);
+    glBindBuffer(GL_ELEMENT_ARRAY_BUFFER, ibo);
+    glBufferData(GL_ELEMENT_ARRAY_BUFFER, (mem::size_of::<u32>() * indices.len()) as GLsizeiptr, indices.as_ptr() as *const c_void, GL_STREAM_DRAW);
+
+    glEnableVertexAttribArray(POSITION_ATTRIB);
+    glVertexAttribPointer(POSITION_ATTRIB, 2, GL_FLOAT, GL_FALSE as GLboolean, stride, null());
+    glEnableVertexAttribArray(TEXCOORD_ATTRIB);
+    glVertexAttribPointer(TEXCOORD_ATTRIB, 2, GL_FLOAT, GL_FALSE as GLboolean, stride, (mem::size_of::<f32>() * 2) as *const c_void);
+    glEnableVertexAttribArray(LOCAL_ATTRIB);
+    glVertexAttribPointer(LOCAL_ATTRIB, 2, GL_FLOAT, GL_FALSE as GLboolean, stride, (mem::size_of::<f32>() * 4) as *const c_void);
+}
+
+unsafe fn disable_vertex_attribs() {
+    glDisableVertexAttribArray(POSITION_ATTRIB);
+    glDisableVertexAttribArray(TEXCOORD_ATTRIB);
+    glDisableVertexAttribArray(LOCAL_ATTRIB);
+    glBindBuffer(GL_ELEMENT_ARRAY_BUFFER, 0);
+    glBindBuffer(GL_ARRAY_BUFFER, 0);
+}
+
+unsafe fn shader_info_log(object: GLuint, is_program: bool) -> String {
+    let mut length: GLint = 0;
+    if is_program {
+        glGetProgramiv(object, GL_INFO_LOG_LENGTH, &mut length);
+    } else {
+        glGetShaderiv(object, GL_INFO_LOG_LENGTH, &mut length);
+    }
+    let mut log = vec![0u8; length.max(1) as usize];
+    let mut written: GLsizei = 0;
+    if is_program {
+        glGetProgramInfoLog(object, log.len() as GLsizei, &mut written, log.as_mut_ptr() as *mut GLchar);
+    } else {
+        glGetShaderInfoLog(object, log.len() as GLsizei, &mut written, log.as_mut_ptr() as *mut GLchar);
+    }
+    log.truncate(written.max(0) as usize);
+    String::from_utf8_lossy(&log).into_owned()
+}
+
+unsafe fn compile_shader(kind: GLenum, source: &str) -> Result<GLuint, String> {
+    let shader = glCreateShader(kind);
+    let source = CString::new(source).map_err(|_| "shader source has a nul byte in it".to_string())?;
+    let source_ptr = source.as_ptr();
+    glShaderSource(shader, 1, &source_ptr, null());
+    glCompileShader(shader);
+    let mut status: GLint = 0;
+    glGetShaderiv(shader, GL_COMPILE_STATUS, &mut status);
+    if status == GL_FALSE as GLint {
+        let log = shader_info_log(shader, false);
+        glDeleteShader(shader);
+        return Err(log);
+    }
+    Ok(shader)
+}
+
+// compiles and links a vertex + fragment shader pair, returning the compiler's log if anything goes wrong
+pub fn compile_shader_program(vertex_source: &str, fragment_source: &str) -> Result<GLuint, String> {
+    unsafe {
+        let vertex_shader = compile_shader(GL_VERTEX_SHADER, vertex_source)
+            .map_err(|log| format!("vertex shader failed to compile: {}", log))?;
+        let fragment_shader = match compile_shader(GL_FRAGMENT_SHADER, fragment_source) {
+            Ok(shader) => shader,
+            Err(log) => {
+                glDeleteShader(vertex_shader);
+                return Err(format!("fragment shader failed to compile: {}", log));
+            }
+        };
+
+        let program = glCreateProgram();
+        glAttachShader(program, vertex_shader);
+        glAttachShader(program, fragment_shader);
+        glBindAttribLocation(program, POSITION_ATTRIB, b"position\0".as_ptr() as *const GLchar);
+        glBindAttribLocation(program, TEXCOORD_ATTRIB, b"texcoord\0".as_ptr() as *const GLchar);
+        glBindAttribLocation(program, LOCAL_ATTRIB, b"local\0".as_ptr() as *const GLchar);
+        glLinkProgram(program);
+        // the program keeps them alive for as long as it needs them
+        glDeleteShader(vertex_shader);
+        glDeleteShader(fragment_shader);
+
+        let mut status: GLint = 0;
+        glGetProgramiv(program, GL_LINK_STATUS, &mut status);
+        if status == GL_FALSE as GLint {
+            let log = shader_info_log(program, true);
+            glDeleteProgram(program);
+            return Err(format!("shader program failed to link: {}", log));
+        }
+        Ok(program)
+    }
+}
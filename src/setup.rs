@@ -1,12 +1,12 @@
 use std::ffi::{c_void, CStr};
-use std::num::NonZeroU32;
-use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong};
+use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong};
 use std::{mem, ptr};
 use std::ptr::{null, null_mut};
-use libsex::bindings::{_XImage_funcs, _XTransform, AllocNone, CompositeRedirectAutomatic, CompositeRedirectManual, CopyFromParent, CPSubwindowMode, CWColormap, CWEventMask, Display, ExposureMask, GC, GCForeground, GCGraphicsExposures, GL_FALSE, GLbyte, GLfloat, GLubyte, glViewport, GLX_BIND_TO_TEXTURE_RGB_EXT, GLX_BIND_TO_TEXTURE_RGBA_EXT, GLX_BIND_TO_TEXTURE_TARGETS_EXT, GLX_DEPTH_SIZE, GLX_DOUBLEBUFFER, GLX_DRAWABLE_TYPE, GLX_NONE, GLX_PIXMAP_BIT, GLX_RED_SIZE, GLX_RGBA, GLX_TEXTURE_2D_BIT_EXT, GLX_Y_INVERTED_EXT, glXChooseVisual, GLXContext, glXCreateContext, GLXDrawable, glXGetFBConfigAttrib, glXGetFBConfigs, glXGetProcAddress, glXGetProcAddressARB, glXGetVisualFromFBConfig, glXMakeCurrent, IncludeInferiors, InputOutput, LSBFirst, PictFormat, PictOpSrc, Picture, PropertyChangeMask, Screen, ShapeBounding, ShapeInput, StructureNotifyMask, SubstructureNotifyMask, SubstructureRedirectMask, Visual, VisualNoMask, Window, X_RenderQueryPictFormats, XChangeWindowAttributes, XCompositeGetOverlayWindow, XCompositeQueryExtension, XCompositeRedirectSubwindows, XCopyPlane, XCreateBitmapFromData, XCreateColormap, XCreateGC, XCreateImage, XCreatePixmap, XCreateWindow, XDefaultDepth, XDefaultDepthOfScreen, XDefaultRootWindow, XDefaultVisual, XDefaultVisualOfScreen, XDestroyWindow, XFixed, XFixesCreateRegion, XFixesDestroyRegion, XFixesSetWindowShapeRegion, XFixesShowCursor, XFlush, XFree, XFreePixmap, XGCValues, XGetErrorText, XGetVisualInfo, XImage, XInitImage, XLowerWindow, XMapWindow, XOpenDisplay, XPutImage, XRenderComposite, XRenderCreatePicture, XRenderDirectFormat, XRenderFindVisualFormat, XRenderPictFormat, XRenderPictureAttributes, XRenderSetPictureTransform, XReparentWindow, XRootWindow, XScreenNumberOfScreen, XSelectInput, XSetErrorHandler, XSetWindowAttributes, XSync, XTransform, XVisualIDFromVisual, XVisualInfo, ZPixmap};
-use stb_image::image::LoadResult;
-use crate::{allow_input_passthrough, fr, get_window_fb_config, rgba_to_bgra};
-use crate::helpers::redraw_desktop;
+use libsex::bindings::{Pixmap, PropModeReplace, XChangeProperty, _XImage_funcs, _XTransform, AllocNone, CompositeRedirectAutomatic, CompositeRedirectManual, CopyFromParent, CPSubwindowMode, CWColormap, CWEventMask, Display, ExposureMask, GC, GCForeground, GCGraphicsExposures, GL_FALSE, GLbyte, GLfloat, GLubyte, glViewport, GLX_BIND_TO_TEXTURE_RGB_EXT, GLX_BIND_TO_TEXTURE_RGBA_EXT, GLX_BIND_TO_TEXTURE_TARGETS_EXT, GLX_DEPTH_SIZE, GLX_DOUBLEBUFFER, GLX_DRAWABLE_TYPE, GLX_NONE, GLX_PIXMAP_BIT, GLX_RED_SIZE, GLX_RGBA, GLX_TEXTURE_2D_BIT_EXT, GLX_Y_INVERTED_EXT, glXChooseVisual, GLXContext, glXCreateContext, GLXDrawable, glXGetFBConfigAttrib, glXGetFBConfigs, glXGetProcAddress, glXGetProcAddressARB, glXGetVisualFromFBConfig, glXMakeCurrent, IncludeInferiors, InputOnly, InputOutput, LSBFirst, PictFormat, PictOpSrc, Picture, PropertyChangeMask, Screen, ShapeBounding, ShapeInput, StructureNotifyMask, SubstructureNotifyMask, SubstructureRedirectMask, Visual, VisualNoMask, Window, X_RenderQueryPictFormats, XChangeWindowAttributes, XCompositeGetOverlayWindow, XCompositeQueryExtension, XCompositeRedirectSubwindows, XCompositeUnredirectSubwindows, XCopyPlane, XCreateBitmapFromData, XCreateColormap, XCreateGC, XCreateImage, XCreatePixmap, XCreateWindow, XDefaultDepth, XDefaultDepthOfScreen, XDefaultRootWindow, XDefaultVisual, XDefaultVisualOfScreen, XDestroyWindow, XFixed, XFixesCreateRegion, XFixesDestroyRegion, XFixesSetWindowShapeRegion, XFixesShowCursor, XFlush, XFree, XFreePixmap, XGCValues, XGetErrorText, XGetVisualInfo, XImage, XInitImage, XLowerWindow, XMapWindow, XOpenDisplay, XPutImage, XRenderComposite, XRenderFreePicture, XResizeWindow, XRRQueryExtension, XRRSelectInput, RRScreenChangeNotifyMask, XRenderCreatePicture, XRenderDirectFormat, XRenderFindVisualFormat, XRenderPictFormat, XRenderPictureAttributes, XRenderSetPictureTransform, XReparentWindow, XRootWindow, XScreenNumberOfScreen, XSelectInput, XSetErrorHandler, XSetWindowAttributes, XSync, XTransform, XUnmapWindow, XVisualIDFromVisual, XVisualInfo, ZPixmap};
+use crate::{allow_input_passthrough, get_window_fb_config, rgba_to_bgra};
+use crate::config::Config;
+use crate::helpers::{get_atom, redraw_desktop, set_cardinal_property, set_utf8_list_property, set_utf8_property};
+use crate::wallpaper::RgbaImage;
 
 pub fn setup_compositing(display: *mut Display, root: Window) -> (Window, GC) {
     let mut major = 0;
@@ -87,7 +87,7 @@ pub fn setup_compositing(display: *mut Display, root: Window) -> (Window, GC) {
 }
 
 pub fn setup_desktop(display: *mut Display, gc: GC, screen: *mut Screen, pict_format: *mut XRenderPictFormat, root: Window,
-                     src_width: u16, src_height: u16) -> (Window, Picture){
+                     src_width: u16, src_height: u16) -> (Window, Picture, Pixmap){
 
     let desktop = unsafe { XCreateWindow(display, root,
                                          0, 0,
@@ -113,97 +113,34 @@ pub fn setup_desktop(display: *mut Display, gc: GC, screen: *mut Screen, pict_fo
         XSync(display, 0);
     }
 
-    // load the bg.png image
-    let bg_image_load = stb_image::image::load("bg.png");
-    let bg_image = match bg_image_load {
-        LoadResult::ImageU8(image) => image,
-        LoadResult::ImageF32(_) => panic!("bg.png is not 8-bit"),
-        LoadResult::Error(e) => panic!("Error loading bg.png: {}", e),
-    };
-
-    let bg_image_width = NonZeroU32::new(bg_image.width as u32).unwrap();
-    let bg_image_height = NonZeroU32::new(bg_image.height as u32).unwrap();
-
-    let mut divide_factor = 1;
-    let mut potential_size: u32 = (src_width / divide_factor) as u32 * (src_height / divide_factor) as u32;
-    // calculate the amount of bytes that src_width * src_height * 4 will take
-    while potential_size > 300000 {
-        divide_factor += 1;
-        potential_size = (src_width / divide_factor) as u32 * (src_height / divide_factor) as u32;
-    }
-
-    let mut src = fr::Image::from_vec_u8(
-        bg_image_width,
-        bg_image_height,
-        bg_image.data,
-        fr::PixelType::U8x4,
-    ).unwrap();
-    // Create MulDiv instance
-    let alpha_mul_div = fr::MulDiv::default();
-    // Multiple RGB channels of source image by alpha channel
-    alpha_mul_div
-        .multiply_alpha_inplace(&mut src.view_mut())
-        .unwrap();
-
-    let dst_width = NonZeroU32::new((src_width / divide_factor) as u32).unwrap();
-    let dst_height = NonZeroU32::new((src_height / divide_factor) as u32).unwrap();
-    let mut dst_image = fr::Image::new(
-        dst_width,
-        dst_height,
-        fr::PixelType::U8x4,
-    );
-    let mut dst_view = dst_image.view_mut();
-
-    let mut resizer = fr::Resizer::new(
-        fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3),
-    );
-    resizer.resize(&src.view(), &mut dst_view).unwrap();
-
-    alpha_mul_div.divide_alpha_inplace(&mut dst_view).unwrap();
-
-    let mut bg_image_buffer = dst_image.buffer();
-
-    // create a vec from the buffer so we can make it mutable
-    let mut bg_image_vec: Vec<u8> = bg_image_buffer.to_vec();
+    let (picture, pixmap) = create_desktop_picture(display, screen, root, desktop, src_width, src_height);
 
-    // create a pixmap to draw on
-    let mut pixmap = unsafe {
-        XCreatePixmap(display, desktop,
-                      (src_width / divide_factor) as c_uint, (src_height / divide_factor) as c_uint,
-                      24)
-    };
+    redraw_desktop(display, picture, desktop, pict_format, src_width as u32, src_height as u32);
+    //allow_input_passthrough(display, desktop, 0, 0);
 
     unsafe {
         XSync(display, 0);
     }
 
-    let mut img: *mut XImage = unsafe { mem::zeroed() };
-
-    let mut data = rgba_to_bgra(&bg_image_vec);
-
-    img = unsafe {
-        XCreateImage(display, XDefaultVisualOfScreen(screen), 24, ZPixmap as c_int, 0,
-                     data.as_mut_ptr() as *mut c_char,
-                     (src_width / divide_factor) as c_uint, (src_height / divide_factor) as c_uint, 32, 0)
-    };
-
+    // map the window
     unsafe {
-        XSync(display, 0);
+        XMapWindow(display, desktop);
+        XLowerWindow(display, desktop);
     }
 
+    (desktop, picture, pixmap)
+}
 
-    unsafe {
-        XInitImage(img);
-    }
-    unsafe {
-        XSync(display, 0);
-    }
+// the screen sized pixmap the wallpaper goes on and a picture of it, which also gets published as the root pixmap
+fn create_desktop_picture(display: *mut Display, screen: *mut Screen, root: Window, desktop: Window,
+                          src_width: u16, src_height: u16) -> (Picture, Pixmap) {
+    // create a pixmap to draw on
+    let pixmap = unsafe {
+        XCreatePixmap(display, desktop,
+                      src_width as c_uint, src_height as c_uint,
+                      24)
+    };
 
-    // put the image on the pixmap
-    unsafe {
-        XPutImage(display, pixmap, gc, img, 0, 0, 0, 0,
-                  (src_width / divide_factor) as c_uint, (src_height / divide_factor) as c_uint);
-    }
     unsafe {
         XSync(display, 0);
     }
@@ -232,40 +169,191 @@ pub fn setup_desktop(display: *mut Display, gc: GC, screen: *mut Screen, pict_fo
         XSync(display, 0);
     }
 
-    // set picture transform
+    // so pseudo-transparent terminals (and anything else that reads the root pixmap) can see the wallpaper
+    for property in ["_XROOTPMAP_ID", "ESETROOT_PMAP_ID"] {
+        unsafe {
+            XChangeProperty(display, root, get_atom(display, property), get_atom(display, "PIXMAP"), 32,
+                            PropModeReplace as c_int, &pixmap as *const Pixmap as *const c_uchar, 1);
+        }
+    }
+
+    (picture, pixmap)
+}
+
+// the screen changed size: the desktop window grows (or shrinks) to match and gets a new pixmap, which the caller
+// has to put the wallpaper on again
+pub fn resize_desktop(display: *mut Display, screen: *mut Screen, root: Window, desktop: Window, picture: Picture, pixmap: Pixmap,
+                      src_width: u16, src_height: u16) -> (Picture, Pixmap) {
     unsafe {
-        XRenderSetPictureTransform(display, picture, &mut XTransform {
-            matrix: [
-                [1.0 as XFixed, 0.0 as XFixed, 0.0 as XFixed],
-                [0.0 as XFixed, 1.0 as XFixed, 0.0 as XFixed],
-                [0.0 as XFixed, 0.0 as XFixed, divide_factor as XFixed]
-            ]
-        });
+        XRenderFreePicture(display, picture);
+        XFreePixmap(display, pixmap);
+        XResizeWindow(display, desktop, src_width as c_uint, src_height as c_uint);
     }
+    create_desktop_picture(display, screen, root, desktop, src_width, src_height)
+}
+
+// asks xrandr to tell us whenever monitors are plugged in, unplugged or rearranged;
+// returns the number its events start at, or None if there's no xrandr
+pub fn setup_randr(display: *mut Display, root: Window) -> Option<c_int> {
+    let mut event_base = 0;
+    let mut error_base = 0;
     unsafe {
-        XSync(display, 0);
+        if XRRQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+            println!("xrandr not found, the whole screen is one monitor");
+            return None;
+        }
+        XRRSelectInput(display, root, RRScreenChangeNotifyMask as c_int);
+    }
+    Some(event_base)
+}
+
+// draws a screen sized wallpaper onto the desktop's pixmap, which is also the root pixmap other programs see
+pub fn put_wallpaper(display: *mut Display, gc: GC, screen: *mut Screen, pixmap: Pixmap, image: &RgbaImage) {
+    let mut img: *mut XImage = unsafe { mem::zeroed() };
+
+    let mut data = rgba_to_bgra(&image.data);
+
+    img = unsafe {
+        XCreateImage(display, XDefaultVisualOfScreen(screen), 24, ZPixmap as c_int, 0,
+                     data.as_mut_ptr() as *mut c_char,
+                     image.width as c_uint, image.height as c_uint, 32, 0)
+    };
+    if img.is_null() {
+        println!("could not create an image for the wallpaper");
+        return;
     }
 
     unsafe {
         XSync(display, 0);
     }
 
-    redraw_desktop(display, picture, desktop, pict_format, src_width as u32, src_height as u32);
-    //allow_input_passthrough(display, desktop, 0, 0);
 
+    unsafe {
+        XInitImage(img);
+    }
     unsafe {
         XSync(display, 0);
     }
 
-    // map the window
+    // put the image on the pixmap
     unsafe {
-        XMapWindow(display, desktop);
-        XLowerWindow(display, desktop);
+        XPutImage(display, pixmap, gc, img, 0, 0, 0, 0,
+                  image.width as c_uint, image.height as c_uint);
+    }
+    unsafe {
+        XSync(display, 0);
+    }
+    // the pixels belong to data, so take them away from the image before freeing it or x would free them too
+    unsafe {
+        (*img).data = null_mut();
+        XFree(img as *mut c_void);
     }
+}
 
-    (desktop, picture)
+// turns compositing off and on again, for when a fullscreen window can go straight to the screen. every window is
+// redirected as one of root's subwindows, so they all get unredirected together; the overlay goes away too,
+// otherwise it'd sit on top of the window showing whatever we drew last
+pub fn set_redirected(display: *mut Display, root: Window, overlay_window: Window, redirected: bool) {
+    unsafe {
+        if redirected {
+            XCompositeRedirectSubwindows(display, root, CompositeRedirectManual as c_int);
+            XMapWindow(display, overlay_window);
+        } else {
+            XUnmapWindow(display, overlay_window);
+            XCompositeUnredirectSubwindows(display, root, CompositeRedirectManual as c_int);
+        }
+        XSync(display, 0);
+    }
 }
 
+// the ewmh hints we understand, for pagers and taskbars that check before using them
+const SUPPORTED_HINTS: &[&str] = &[
+    "_NET_SUPPORTED",
+    "_NET_SUPPORTING_WM_CHECK",
+    "_NET_CLIENT_LIST",
+    "_NET_NUMBER_OF_DESKTOPS",
+    "_NET_CURRENT_DESKTOP",
+    "_NET_DESKTOP_NAMES",
+    "_NET_DESKTOP_GEOMETRY",
+    "_NET_DESKTOP_VIEWPORT",
+    "_NET_WM_DESKTOP",
+    "_NET_WM_WINDOW_TYPE",
+    "_NET_WM_WINDOW_OPACITY",
+    "_NET_WM_ICON_GEOMETRY",
+    "_NET_WM_STATE",
+    "_NET_WM_STATE_FULLSCREEN",
+    "_NET_WM_STATE_MAXIMIZED_VERT",
+    "_NET_WM_STATE_MAXIMIZED_HORZ",
+    "_NET_WM_STATE_ABOVE",
+    "_NET_WM_STATE_BELOW",
+    "_NET_WM_STATE_STICKY",
+    "_NET_WM_STATE_HIDDEN",
+    "_NET_WM_STATE_SHADED",
+    "_NET_WM_STATE_SKIP_TASKBAR",
+    "_NET_WM_STATE_DEMANDS_ATTENTION",
+    "_NET_WM_ALLOWED_ACTIONS",
+    "_NET_WM_ACTION_MOVE",
+    "_NET_WM_ACTION_RESIZE",
+    "_NET_WM_ACTION_MINIMIZE",
+    "_NET_WM_ACTION_SHADE",
+    "_NET_WM_ACTION_STICK",
+    "_NET_WM_ACTION_MAXIMIZE_HORZ",
+    "_NET_WM_ACTION_MAXIMIZE_VERT",
+    "_NET_WM_ACTION_FULLSCREEN",
+    "_NET_WM_ACTION_CHANGE_DESKTOP",
+    "_NET_WM_ACTION_ABOVE",
+    "_NET_WM_ACTION_BELOW",
+    "_NET_WM_BYPASS_COMPOSITOR",
+    "_NET_WM_STRUT",
+    "_NET_WM_STRUT_PARTIAL",
+    "_NET_WORKAREA",
+    "_NET_FRAME_EXTENTS",
+    "_NET_REQUEST_FRAME_EXTENTS",
+];
+
+// what pagers and taskbars can ask us to do to any managed window, for _NET_WM_ALLOWED_ACTIONS
+pub const ALLOWED_ACTIONS: &[&str] = &[
+    "_NET_WM_ACTION_MOVE",
+    "_NET_WM_ACTION_RESIZE",
+    "_NET_WM_ACTION_MINIMIZE",
+    "_NET_WM_ACTION_SHADE",
+    "_NET_WM_ACTION_STICK",
+    "_NET_WM_ACTION_MAXIMIZE_HORZ",
+    "_NET_WM_ACTION_MAXIMIZE_VERT",
+    "_NET_WM_ACTION_FULLSCREEN",
+    "_NET_WM_ACTION_CHANGE_DESKTOP",
+    "_NET_WM_ACTION_ABOVE",
+    "_NET_WM_ACTION_BELOW",
+];
+
+// publishes what we support and the workspaces on the root window, starting on the first workspace
+// returns the _NET_SUPPORTING_WM_CHECK window, which isn't a client and shouldn't get managed
+pub fn setup_ewmh(display: *mut Display, root: Window, config: &Config, src_width: u32, src_height: u32) -> Window {
+    let supported: Vec<u64> = SUPPORTED_HINTS.iter().map(|hint| get_atom(display, hint) as u64).collect();
+    set_cardinal_property(display, root, "_NET_SUPPORTED", "ATOM", &supported);
+
+    // an invisible child of root that points at itself, so pagers and panels can tell a compliant wm is running and what it's called
+    let check_window = unsafe {
+        XCreateWindow(display, root, -1, -1, 1, 1, 0, 0, InputOnly as c_uint, null_mut(), 0, null_mut())
+    };
+    set_cardinal_property(display, check_window, "_NET_SUPPORTING_WM_CHECK", "WINDOW", &[check_window as u64]);
+    set_utf8_property(display, check_window, "_NET_WM_NAME", "cwm");
+    set_cardinal_property(display, root, "_NET_SUPPORTING_WM_CHECK", "WINDOW", &[check_window as u64]);
+    // nothing's managed yet
+    set_cardinal_property(display, root, "_NET_CLIENT_LIST", "WINDOW", &[]);
+
+    set_cardinal_property(display, root, "_NET_NUMBER_OF_DESKTOPS", "CARDINAL", &[config.workspaces as u64]);
+    set_cardinal_property(display, root, "_NET_CURRENT_DESKTOP", "CARDINAL", &[0]);
+    let names: Vec<String> = (0..config.workspaces).map(|workspace| config.workspace_name(workspace)).collect();
+    set_utf8_list_property(display, root, "_NET_DESKTOP_NAMES", &names);
+    // workspaces are all screen sized, nothing scrolls
+    set_cardinal_property(display, root, "_NET_DESKTOP_GEOMETRY", "CARDINAL", &[src_width as u64, src_height as u64]);
+    set_cardinal_property(display, root, "_NET_DESKTOP_VIEWPORT", "CARDINAL", &vec![0; config.workspaces * 2]);
+    unsafe {
+        XFlush(display);
+    }
+    check_window
+}
 
 pub unsafe fn setup_glx(display: *mut Display, overlay: Window, src_width: u32, src_height: u32, screen: *mut Screen)
     -> (GLXContext, *mut XVisualInfo, libsex::bindings::GLXFBConfig,
@@ -0,0 +1,90 @@
+// user supplied fragment shaders from the config, reloaded whenever their files change
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use libsex::bindings::*;
+use crate::renderer::{compile_shader_program, VERTEX_SHADER};
+
+// how often to look at the files for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+struct CustomShader {
+    path: PathBuf,
+    modified: Option<SystemTime>, // when the file was last changed, as of the last time we loaded it
+    program: GLuint, // 0 if it didn't compile, which means the default shader gets used instead
+}
+
+impl CustomShader {
+    fn load(&mut self) {
+        self.modified = modified_time(&self.path);
+        let source = match fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(e) => {
+                println!("could not read shader {}: {}", self.path.display(), e);
+                self.delete();
+                return;
+            }
+        };
+        match compile_shader_program(VERTEX_SHADER, &source) {
+            Ok(program) => {
+                self.delete();
+                self.program = program;
+                println!("loaded shader {}", self.path.display());
+            }
+            Err(e) => {
+                println!("shader {} didn't work, using the default one: {}", self.path.display(), e);
+                self.delete();
+            }
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.program != 0 {
+            unsafe {
+                glDeleteProgram(self.program);
+            }
+        }
+        self.program = 0;
+    }
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// shaders are referred to by their index in config.shaders
+pub struct CustomShaders {
+    shaders: Vec<CustomShader>,
+    last_check: Instant,
+}
+
+impl CustomShaders {
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let mut shaders = CustomShaders {
+            shaders: paths.iter().map(|path| CustomShader { path: path.clone(), modified: None, program: 0 }).collect(),
+            last_check: Instant::now(),
+        };
+        for shader in shaders.shaders.iter_mut() {
+            shader.load();
+        }
+        shaders
+    }
+
+    // the program to use for a shader, or 0 (the default shader) if there isn't one or it's broken
+    pub fn program(&self, id: Option<usize>) -> GLuint {
+        id.and_then(|id| self.shaders.get(id)).map_or(0, |shader| shader.program)
+    }
+
+    // recompiles any shader whose file changed since it was loaded; cheap to call every frame
+    pub fn reload_changed(&mut self) {
+        if self.last_check.elapsed() < RELOAD_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+        for shader in self.shaders.iter_mut() {
+            if modified_time(&shader.path) != shader.modified {
+                shader.load();
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use libsex::bindings::*;
+
+// what gets handed to the drawing code for one window's shadow
+#[derive(Clone, Copy)]
+pub struct Shadow {
+    pub texture: GLuint, // alpha-only, covers the frame plus radius on every side
+    pub radius: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub color: [f32; 3],
+    pub opacity: f32,
+}
+
+// blurred shadow textures, one per frame size and corner radius, since generating them is slow and windows rarely change size
+pub struct ShadowCache {
+    textures: HashMap<(u16, u16, u16), GLuint>,
+    evicted: Vec<GLuint>, // thrown out of the cache, but maybe still queued for drawing this frame
+    radius: u16,
+}
+
+// this many cached sizes before we throw them all away (resizing a window makes a new one every frame)
+const MAX_CACHED_SHADOWS: usize = 64;
+
+impl ShadowCache {
+    pub fn new(radius: u16) -> Self {
+        ShadowCache {
+            textures: HashMap::new(),
+            evicted: Vec::new(),
+            radius,
+        }
+    }
+
+    pub fn get(&mut self, frame_width: u16, frame_height: u16, corner_radius: u16) -> GLuint {
+        if let Some(texture) = self.textures.get(&(frame_width, frame_height, corner_radius)) {
+            return *texture;
+        }
+        if self.textures.len() >= MAX_CACHED_SHADOWS {
+            self.evicted.extend(self.textures.drain().map(|(_, texture)| texture));
+        }
+        let texture = generate_shadow(frame_width as usize, frame_height as usize, self.radius as usize, corner_radius as f32);
+        self.textures.insert((frame_width, frame_height, corner_radius), texture);
+        texture
+    }
+
+    // call once the frame's been drawn, when nothing can be using the evicted textures any more
+    pub fn delete_evicted(&mut self) {
+        for texture in self.evicted.drain(..) {
+            unsafe {
+                glDeleteTextures(1, &texture);
+            }
+        }
+    }
+}
+
+// the solid part of the shadow before blurring: the frame, with rounded corners, antialiased
+fn rounded_rect_mask(width: usize, height: usize, padding: usize, corner_radius: f32) -> Vec<f32> {
+    let size_x = width + padding * 2;
+    let size_y = height + padding * 2;
+    let corner_radius = corner_radius.min(width as f32 / 2.0).min(height as f32 / 2.0).max(0.0);
+    let half_width = width as f32 / 2.0;
+    let half_height = height as f32 / 2.0;
+    let mut mask = Vec::with_capacity(size_x * size_y);
+    for y in 0..size_y {
+        for x in 0..size_x {
+            // signed distance to the rounded rectangle, same as the window shader uses
+            let px = (x as f32 + 0.5 - padding as f32 - half_width).abs() - (half_width - corner_radius);
+            let py = (y as f32 + 0.5 - padding as f32 - half_height).abs() - (half_height - corner_radius);
+            let outside = (px.max(0.0).powi(2) + py.max(0.0).powi(2)).sqrt();
+            let distance = outside + px.max(py).min(0.0) - corner_radius;
+            mask.push((0.5 - distance).clamp(0.0, 1.0));
+        }
+    }
+    mask
+}
+
+// one pass of a gaussian blur, along the rows if horizontal, otherwise down the columns
+fn blur_pass(values: &[f32], kernel: &[f32], radius: usize, lines: usize, line_length: usize, horizontal: bool) -> Vec<f32> {
+    let mut result = vec![0.0; values.len()];
+    for line in 0..lines {
+        for i in 0..line_length {
+            let mut value = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let j = i as isize + k as isize - radius as isize;
+                if j < 0 || j as usize >= line_length {
+                    continue;
+                }
+                let index = if horizontal { line * line_length + j as usize } else { j as usize * lines + line };
+                value += values[index] * weight;
+            }
+            let index = if horizontal { line * line_length + i } else { i * lines + line };
+            result[index] = value;
+        }
+    }
+    result
+}
+
+fn generate_shadow(frame_width: usize, frame_height: usize, radius: usize, corner_radius: f32) -> GLuint {
+    let size_x = frame_width + radius * 2;
+    let size_y = frame_height + radius * 2;
+    let mut alpha = rounded_rect_mask(frame_width, frame_height, radius, corner_radius);
+    if radius > 0 {
+        // gaussian blurs are separable whatever the shape, so blur across then down
+        let sigma = radius as f32 / 2.0;
+        let kernel: Vec<f32> = (-(radius as isize)..=radius as isize)
+            .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let kernel_sum: f32 = kernel.iter().sum();
+        let kernel: Vec<f32> = kernel.iter().map(|weight| weight / kernel_sum).collect();
+        alpha = blur_pass(&alpha, &kernel, radius, size_y, size_x, true);
+        alpha = blur_pass(&alpha, &kernel, radius, size_x, size_y, false);
+    }
+    let alpha: Vec<u8> = alpha.iter().map(|value| (value * 255.0) as u8).collect();
+
+    let mut texture: GLuint = 0;
+    unsafe {
+        glGenTextures(1, &mut texture);
+        glBindTexture(GL_TEXTURE_2D, texture);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MIN_FILTER, GL_LINEAR as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_MAG_FILTER, GL_LINEAR as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_S, GL_CLAMP_TO_EDGE as GLint);
+        glTexParameteri(GL_TEXTURE_2D, GL_TEXTURE_WRAP_T, GL_CLAMP_TO_EDGE as GLint);
+        // rows of one byte each aren't 4-byte aligned
+        glPixelStorei(GL_UNPACK_ALIGNMENT, 1);
+        glTexImage2D(GL_TEXTURE_2D, 0,
+                     GL_ALPHA as GLint, size_x as i32, size_y as i32,
+                     0, GL_ALPHA,
+                     GL_UNSIGNED_BYTE, alpha.as_ptr() as *const c_void);
+        glPixelStorei(GL_UNPACK_ALIGNMENT, 4);
+    }
+    texture
+}
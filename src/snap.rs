@@ -0,0 +1,115 @@
+// snapping a window while it's dragged around: to the edges of the screen, to other windows, and into halves of the screen
+use libsex::bindings::GL_TRIANGLES;
+use crate::layout::{self, Columns, LayoutSettings, Monocle, Rect};
+use crate::renderer::{DrawParams, Fill, Renderer};
+
+// how close the cursor has to get to the edge of the screen to tile the window there
+const EDGE_ZONE: i32 = 4;
+
+// where the window would go if it was dropped with the cursor at (cursor_x, cursor_y): the top edge maximizes it,
+// the sides tile it into that half of area. gaps are the same as the tiling layouts use
+pub fn edge_tile(cursor_x: i32, cursor_y: i32, area: Rect, settings: &LayoutSettings) -> Option<Rect> {
+    if cursor_y < area.y + EDGE_ZONE {
+        return layout::arrange(&Monocle, area, 1, settings).first().copied();
+    }
+    let halves = layout::arrange(&Columns, area, 2, settings);
+    if cursor_x < area.x + EDGE_ZONE {
+        return halves.first().copied();
+    }
+    if cursor_x >= area.x + area.width - EDGE_ZONE {
+        return halves.get(1).copied();
+    }
+    None
+}
+
+// the smallest of the distances within threshold, if there are any
+fn closest(distances: impl Iterator<Item = i32>, threshold: i32) -> Option<i32> {
+    distances.filter(|distance| distance.abs() <= threshold).min_by_key(|distance| distance.abs())
+}
+
+// moves frame so its edges line up with the edges of area or any of the other windows' frames nearby;
+// returns the new top left corner of the frame
+pub fn snap(frame: Rect, others: &[Rect], area: Rect, threshold: i32) -> (i32, i32) {
+    let (left, right, top, bottom) = (frame.x, frame.x + frame.width, frame.y, frame.y + frame.height);
+
+    let mut x_distances = vec![area.x - left, area.x + area.width - right];
+    let mut y_distances = vec![area.y - top, area.y + area.height - bottom];
+    for other in others {
+        let (other_left, other_right, other_top, other_bottom) = (other.x, other.x + other.width, other.y, other.y + other.height);
+        // only windows that are next to each other snap side by side, and the same for above and below
+        if top < other_bottom && other_top < bottom {
+            // touching, then lined up
+            x_distances.extend_from_slice(&[other_right - left, other_left - right, other_left - left, other_right - right]);
+        }
+        if left < other_right && other_left < right {
+            y_distances.extend_from_slice(&[other_bottom - top, other_top - bottom, other_top - top, other_bottom - bottom]);
+        }
+    }
+
+    let dx = closest(x_distances.into_iter(), threshold).unwrap_or(0);
+    let dy = closest(y_distances.into_iter(), threshold).unwrap_or(0);
+    (frame.x + dx, frame.y + dy)
+}
+
+// a see-through rectangle showing where the window's about to be tiled
+pub fn draw_preview(renderer: &mut Renderer, rect: Rect, color: [f32; 3], corner_radius: f32) {
+    let (x, y, width, height) = (rect.x as f32, rect.y as f32, rect.width as f32, rect.height as f32);
+    let vertices = [
+        x, y, 0.0, 0.0, 0.0, 0.0,
+        x + width, y, 0.0, 0.0, 1.0, 0.0,
+        x, y + height, 0.0, 0.0, 0.0, 1.0,
+        x + width, y + height, 0.0, 0.0, 1.0, 1.0,
+    ];
+    let [r, g, b] = color;
+    renderer.queue(DrawParams::new(Fill::Solid([r, g, b, 0.25]), 1.0, (width, height), corner_radius),
+                   GL_TRIANGLES, &vertices, &[0, 1, 2, 1, 3, 2]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_tile_maximizes_at_the_top_and_halves_at_the_sides() {
+        let area = Rect::new(0, 0, 1000, 600);
+        let settings = LayoutSettings::default();
+        assert_eq!(edge_tile(500, 2, area, &settings), Some(Rect::new(10, 10, 980, 580)));
+        assert_eq!(edge_tile(1, 300, area, &settings), Some(Rect::new(10, 10, 485, 580)));
+        assert_eq!(edge_tile(996, 300, area, &settings), Some(Rect::new(505, 10, 485, 580)));
+        assert_eq!(edge_tile(500, 300, area, &settings), None);
+    }
+
+    #[test]
+    fn edge_tile_uses_the_monitor_it_was_given() {
+        let area = Rect::new(1000, 0, 800, 600);
+        let settings = LayoutSettings::default();
+        assert_eq!(edge_tile(1001, 300, area, &settings), Some(Rect::new(1010, 10, 385, 580)));
+        assert_eq!(edge_tile(1797, 300, area, &settings), Some(Rect::new(1405, 10, 385, 580)));
+        // the edge between the monitors is only an edge for the one the cursor is on
+        assert_eq!(edge_tile(998, 300, Rect::new(0, 0, 1000, 600), &settings), Some(Rect::new(505, 10, 485, 580)));
+        assert_eq!(edge_tile(1010, 300, area, &settings), None);
+    }
+
+    #[test]
+    fn snaps_to_the_screen_edge() {
+        let area = Rect::new(0, 0, 1000, 600);
+        assert_eq!(snap(Rect::new(13, 200, 100, 100), &[], area, 15), (0, 200));
+        assert_eq!(snap(Rect::new(890, 495, 100, 100), &[], area, 15), (900, 500));
+        assert_eq!(snap(Rect::new(300, 300, 100, 100), &[], area, 15), (300, 300));
+    }
+
+    #[test]
+    fn snaps_next_to_other_windows() {
+        let area = Rect::new(0, 0, 1000, 600);
+        let other = Rect::new(0, 90, 200, 150);
+        assert_eq!(snap(Rect::new(205, 100, 100, 100), &[other], area, 10), (200, 100));
+        // not beside it, so its sides don't count
+        assert_eq!(snap(Rect::new(205, 400, 100, 100), &[other], area, 10), (205, 400));
+    }
+
+    #[test]
+    fn closest_edge_wins() {
+        let area = Rect::new(0, 0, 1000, 600);
+        assert_eq!(snap(Rect::new(3, 100, 100, 100), &[Rect::new(105, 100, 50, 50)], area, 15), (5, 100));
+    }
+}
@@ -0,0 +1,101 @@
+// colours for the window borders and the background behind the desktop
+use crate::renderer::Fill;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ThemeMode {
+    Static, // always color
+    Rainbow, // the good old sine wave rainbow, moving by frequency every frame
+    Gradient, // from color to gradient_end, top left to bottom right of the border
+    Pulse, // color, getting brighter and darker while the window has focus
+    Wallpaper, // the most common colour in the wallpaper
+}
+
+impl ThemeMode {
+    pub fn parse(name: &str) -> Option<ThemeMode> {
+        match name {
+            "static" => Some(ThemeMode::Static),
+            "rainbow" => Some(ThemeMode::Rainbow),
+            "gradient" => Some(ThemeMode::Gradient),
+            "pulse" => Some(ThemeMode::Pulse),
+            "wallpaper" => Some(ThemeMode::Wallpaper),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub mode: ThemeMode,
+    pub color: [f32; 3],
+    pub gradient_end: [f32; 3],
+    pub frequency: f32, // radians per frame, for rainbow and pulse
+    pub phase: f32, // radians, so different themes can be out of step with each other
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            mode: ThemeMode::Rainbow,
+            color: [1.0, 1.0, 1.0],
+            gradient_end: [0.0, 0.0, 0.0],
+            frequency: 0.05,
+            phase: 0.0,
+        }
+    }
+}
+
+impl Theme {
+    // the colour at frame t; for gradients that's the colour at the start
+    pub fn color_at(&self, t: u64, wallpaper_color: [f32; 3], focused: bool) -> [f32; 3] {
+        // t keeps growing for as long as we run, so work out the angle in f64 and wrap it to one period before it
+        // goes back to f32, or the colours start stepping after a few hours
+        let angle = ((self.frequency as f64 * t as f64 + self.phase as f64) % std::f64::consts::TAU) as f32;
+        match self.mode {
+            ThemeMode::Static | ThemeMode::Gradient => self.color,
+            ThemeMode::Rainbow => [
+                (angle + 0.0).sin() * 0.5 + 0.5,
+                (angle + 2.0).sin() * 0.5 + 0.5,
+                (angle + 4.0).sin() * 0.5 + 0.5,
+            ],
+            ThemeMode::Pulse => {
+                let brightness = if focused { angle.sin() * 0.25 + 0.75 } else { 1.0 };
+                self.color.map(|channel| channel * brightness)
+            }
+            ThemeMode::Wallpaper => wallpaper_color,
+        }
+    }
+
+    // what to draw a border with at frame t
+    pub fn border_fill(&self, t: u64, wallpaper_color: [f32; 3], focused: bool) -> Fill {
+        let [r, g, b] = self.color_at(t, wallpaper_color, focused);
+        match self.mode {
+            ThemeMode::Gradient => {
+                let [end_r, end_g, end_b] = self.gradient_end;
+                Fill::Gradient([r, g, b, 1.0], [end_r, end_g, end_b, 1.0])
+            }
+            _ => Fill::Solid([r, g, b, 1.0]),
+        }
+    }
+}
+
+// the most common colour in an rgba image, roughly: colours get sorted into 4096 buckets and the fullest one wins
+pub fn dominant_color(rgba: &[u8]) -> [f32; 3] {
+    let mut counts = vec![0u32; 4096];
+    let mut sums = vec![[0u64; 3]; 4096];
+    // a few thousand pixels is plenty to find the dominant colour of a wallpaper
+    let pixels = rgba.len() / 4;
+    let step = (pixels / 8192).max(1);
+    for pixel in rgba.chunks_exact(4).step_by(step) {
+        let bucket = ((pixel[0] as usize >> 4) << 8) | ((pixel[1] as usize >> 4) << 4) | (pixel[2] as usize >> 4);
+        counts[bucket] += 1;
+        for channel in 0..3 {
+            sums[bucket][channel] += pixel[channel] as u64;
+        }
+    }
+    let (bucket, count) = match counts.iter().enumerate().max_by_key(|(_, count)| **count) {
+        Some((bucket, count)) if *count > 0 => (bucket, *count as u64),
+        _ => return [0.0, 0.0, 0.0],
+    };
+    // the average of the bucket rather than its corner, so the colour is one that's actually in the image
+    sums[bucket].map(|sum| (sum / count) as f32 / 255.0)
+}
@@ -0,0 +1,255 @@
+// animated workspace switching: both workspaces get drawn offscreen, then slid, spun or faded into each other
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::time::{Duration, Instant};
+use libsex::bindings::*;
+use crate::animation::AnimationSettings;
+use crate::renderer::{DrawParams, Fill, RenderTarget, Renderer, VERTEX_SIZE};
+
+// the cube faces get cut into this many strips, otherwise the perspective bends the pictures along the diagonal
+const CUBE_STRIPS: usize = 24;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransitionStyle {
+    None, // switch instantly
+    Slide, // the new workspace pushes the old one off the side of the screen
+    Cube, // the workspaces are sides of a spinning cube
+    Fade, // crossfade
+}
+
+impl TransitionStyle {
+    pub fn parse(name: &str) -> Option<TransitionStyle> {
+        match name {
+            "none" => Some(TransitionStyle::None),
+            "slide" => Some(TransitionStyle::Slide),
+            "cube" => Some(TransitionStyle::Cube),
+            "fade" => Some(TransitionStyle::Fade),
+            _ => None,
+        }
+    }
+}
+
+// where a window gets drawn this frame
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Layer {
+    Screen, // nothing's switching, straight to the frame
+    Outgoing, // the workspace we're leaving
+    Incoming, // the workspace we're going to
+    Sticky, // on every workspace, so it stays still on top of the other two
+}
+
+struct Transition {
+    from: usize,
+    to: usize,
+    direction: f32, // 1.0 if the new workspace comes in from the right, -1.0 from the left
+    started: Instant,
+    progress: f32, // as of the last begin_frame, after the curve
+}
+
+pub struct WorkspaceTransitions {
+    style: TransitionStyle,
+    settings: AnimationSettings,
+    width: i32,
+    height: i32,
+    targets: Option<[RenderTarget; 3]>, // outgoing, incoming and sticky; only made once there's a transition to draw
+    current: Option<Transition>,
+    layer: Layer, // where drawing is going right now
+    motion: f32, // how far the workspaces moved sideways this frame, in pixels
+}
+
+fn create_targets(width: i32, height: i32) -> Result<[RenderTarget; 3], String> {
+    Ok([RenderTarget::new(width, height)?, RenderTarget::new(width, height)?, RenderTarget::new(width, height)?])
+}
+
+impl WorkspaceTransitions {
+    pub fn new(style: TransitionStyle, settings: AnimationSettings, width: i32, height: i32) -> Self {
+        WorkspaceTransitions {
+            style,
+            settings,
+            width,
+            height,
+            targets: None,
+            current: None,
+            layer: Layer::Screen,
+            motion: 0.0,
+        }
+    }
+
+    // starts animating from one workspace to another; switching again halfway through starts over from wherever we were going
+    pub fn start(&mut self, from: usize, to: usize) {
+        self.current = None;
+        if self.style == TransitionStyle::None || from == to {
+            return;
+        }
+        if self.targets.is_none() {
+            match create_targets(self.width, self.height) {
+                Ok(targets) => self.targets = Some(targets),
+                Err(e) => {
+                    println!("could not set up workspace transitions, switching instantly: {}", e);
+                    self.style = TransitionStyle::None;
+                    return;
+                }
+            }
+        }
+        self.current = Some(Transition {
+            from,
+            to,
+            direction: if to > from { 1.0 } else { -1.0 },
+            started: Instant::now(),
+            progress: 0.0,
+        });
+    }
+
+    // for when the screen changes size; a transition that's halfway through just finishes
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.current = None;
+        if let Some(targets) = self.targets.take() {
+            for target in targets {
+                target.delete();
+            }
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.current.is_some()
+    }
+
+    // how far the workspaces moved sideways this frame, in pixels (negative is to the left)
+    pub fn motion(&self) -> f32 {
+        self.motion
+    }
+
+    // which layer a window on workspace (None if sticky) goes in, or None if it isn't on screen at all
+    pub fn layer_for(&self, workspace: Option<usize>, current_workspace: usize) -> Option<Layer> {
+        let transition = match &self.current {
+            Some(transition) => transition,
+            None if workspace.map_or(true, |workspace| workspace == current_workspace) => return Some(Layer::Screen),
+            None => return None,
+        };
+        match workspace {
+            None => Some(Layer::Sticky),
+            Some(workspace) if workspace == transition.to => Some(Layer::Incoming),
+            Some(workspace) if workspace == transition.from => Some(Layer::Outgoing),
+            _ => None,
+        }
+    }
+
+    // call before drawing any windows: moves the transition along and clears the offscreen pictures
+    pub fn begin_frame(&mut self, renderer: &mut Renderer) {
+        self.motion = 0.0;
+        self.layer = Layer::Screen;
+        let (transition, targets) = match (&mut self.current, &self.targets) {
+            (Some(transition), Some(targets)) => (transition, targets),
+            _ => return,
+        };
+        let duration = Duration::from_millis(self.settings.duration_ms).as_secs_f32().max(0.001);
+        let progress = self.settings.curve.apply(transition.started.elapsed().as_secs_f32() / duration);
+        // fading doesn't move anything
+        if self.style != TransitionStyle::Fade {
+            self.motion = (progress - transition.progress) * self.width as f32 * -transition.direction;
+        }
+        transition.progress = progress;
+        for target in targets {
+            renderer.bind_target(Some(target));
+            unsafe {
+                glClearColor(0.0, 0.0, 0.0, 0.0);
+                glClear(GL_COLOR_BUFFER_BIT);
+            }
+        }
+        renderer.bind_target(None);
+    }
+
+    // sends whatever gets drawn next to layer
+    pub fn bind(&mut self, renderer: &mut Renderer, layer: Layer) {
+        if layer == self.layer {
+            return;
+        }
+        self.layer = layer;
+        let target = match (&self.targets, layer) {
+            (Some(targets), Layer::Outgoing) => Some(&targets[0]),
+            (Some(targets), Layer::Incoming) => Some(&targets[1]),
+            (Some(targets), Layer::Sticky) => Some(&targets[2]),
+            _ => None,
+        };
+        renderer.bind_target(target);
+    }
+
+    // call after drawing the windows: puts both workspaces on screen mid transition with the sticky windows on top,
+    // and ends the transition once it's done
+    pub fn end_frame(&mut self, renderer: &mut Renderer) {
+        self.bind(renderer, Layer::Screen);
+        let (transition, targets) = match (&self.current, &self.targets) {
+            (Some(transition), Some(targets)) => (transition, targets),
+            _ => return,
+        };
+        let progress = transition.progress;
+        let direction = transition.direction;
+        let size = (self.width as f32, self.height as f32);
+        match self.style {
+            TransitionStyle::Slide => {
+                let offset = -direction * progress * size.0;
+                draw_face(renderer, &targets[0], 1.0, 1, size, |u, v| (offset + u * size.0, v * size.1));
+                draw_face(renderer, &targets[1], 1.0, 1, size, |u, v| (offset + direction * size.0 + u * size.0, v * size.1));
+            }
+            TransitionStyle::Cube => {
+                let angle = -direction * progress * FRAC_PI_2;
+                // the cube backs away halfway through so its edges don't stick out of the screen
+                let zoom = (progress.clamp(0.0, 1.0) * PI).sin() * size.0 * 0.4;
+                let mut faces = [(&targets[0], angle), (&targets[1], angle + direction * FRAC_PI_2)];
+                // the face further away goes first
+                faces.sort_by(|a, b| a.1.cos().total_cmp(&b.1.cos()));
+                for (target, angle) in faces {
+                    // facing away from us, nothing to see
+                    if angle.cos() <= 0.0 {
+                        continue;
+                    }
+                    draw_face(renderer, target, 1.0, CUBE_STRIPS, size, |u, v| cube_position(u, v, angle, zoom, size));
+                }
+            }
+            TransitionStyle::Fade => {
+                draw_face(renderer, &targets[0], 1.0 - progress, 1, size, |u, v| (u * size.0, v * size.1));
+                draw_face(renderer, &targets[1], progress, 1, size, |u, v| (u * size.0, v * size.1));
+            }
+            TransitionStyle::None => {}
+        }
+        draw_face(renderer, &targets[2], 1.0, 1, size, |u, v| (u * size.0, v * size.1));
+
+        if transition.started.elapsed().as_millis() >= self.settings.duration_ms as u128 {
+            self.current = None;
+        }
+    }
+}
+
+// where (u, v) on a cube face turned by angle ends up on screen; at angle 0 the face covers the screen exactly
+fn cube_position(u: f32, v: f32, angle: f32, zoom: f32, size: (f32, f32)) -> (f32, f32) {
+    let half = size.0 / 2.0;
+    let local_x = (u - 0.5) * size.0;
+    let local_y = (v - 0.5) * size.1;
+    // turn around the middle of the cube, z points towards whoever's looking at the screen
+    let x = local_x * angle.cos() + half * angle.sin();
+    let z = half * angle.cos() - local_x * angle.sin();
+    let distance = size.0 * 1.5; // from the viewer to the front of the cube
+    let scale = distance / (distance + zoom + half - z);
+    (size.0 / 2.0 + x * scale, size.1 / 2.0 + local_y * scale)
+}
+
+// draws a layer's picture with (u, v) across it put wherever position says, in vertical strips
+fn draw_face(renderer: &mut Renderer, target: &RenderTarget, opacity: f32, strips: usize, size: (f32, f32), position: impl Fn(f32, f32) -> (f32, f32)) {
+    let mut vertices = Vec::with_capacity((strips + 1) * 2 * VERTEX_SIZE);
+    let mut indices = Vec::with_capacity(strips * 6);
+    for i in 0..=strips {
+        let u = i as f32 / strips as f32;
+        for v in [0.0, 1.0] {
+            let (x, y) = position(u, v);
+            // render targets are upside down compared to the screen
+            vertices.extend_from_slice(&[x, y, u, 1.0 - v, u, v]);
+        }
+        if i < strips {
+            let top_left = (i * 2) as u32;
+            indices.extend_from_slice(&[top_left, top_left + 2, top_left + 1, top_left + 2, top_left + 3, top_left + 1]);
+        }
+    }
+    // whatever got drawn into the layer is premultiplied already
+    renderer.queue(DrawParams::new(Fill::Premultiplied(target.texture), opacity, size, 0.0), GL_TRIANGLES, &vertices, &indices);
+}
@@ -1,4 +1,9 @@
+use std::ptr::null_mut;
 use libsex::bindings::{GLXFBConfig, Window, XEvent};
+use crate::animation::Animation;
+use crate::config::RuleSettings;
+use crate::helpers::WindowTextures;
+use crate::physics::WobblyMesh;
 
 #[derive(Clone, Copy)]
 pub struct CumWindow {
@@ -14,10 +19,188 @@ pub struct CumWindow {
     pub use_actual_position: bool, // should we render at the window's actual position, or the position we want it to be at?
     pub event: Option<XEvent>, // an associated event
     pub velocity: XVelocity, // the velocity of the window
+    pub minimized: bool, // whether the window is iconified (so mapping it again means restoring it)
+    pub opacity: f32, // opacity we're drawing the window at right now, fades towards the wanted opacity
+    pub net_wm_opacity: f32, // opacity the client (or transset) asked for with _NET_WM_WINDOW_OPACITY
+    pub rule: RuleSettings, // config rules matching the window's WM_CLASS
+    pub window_type: WindowType, // from _NET_WM_WINDOW_TYPE
+    pub workspace: Option<usize>, // which workspace the window is on, None if it's sticky and on all of them
+    pub workspace_hidden: bool, // we unmapped it because its workspace isn't the current one
+    pub floating: bool, // left alone by tiling layouts
+    pub state: WindowState, // from _NET_WM_STATE
+    pub decorated: bool, // has a frame; docks, menus, tooltips and the like don't
+    pub strut: Option<[u32; 12]>, // space it keeps free along the screen edges, from _NET_WM_STRUT_PARTIAL or _NET_WM_STRUT
+    pub transient_for: Option<Window>, // the window a dialog belongs to, from WM_TRANSIENT_FOR
+    pub bypass_compositor: u64, // _NET_WM_BYPASS_COMPOSITOR: 0 doesn't care, 1 would like to be unredirected, 2 wants to stay composited
+}
+
+impl CumWindow {
+    // shaded windows are rolled up to just their title bar
+    pub fn frame_height(&self) -> u16 {
+        if self.state.shaded { 20 } else { self.height + 25 }
+    }
+}
+
+// a plain normal window at 0,0 with nothing set, to fill in the rest of a CumWindow from
+impl Default for CumWindow {
+    fn default() -> Self {
+        CumWindow {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            window_id: 0,
+            frame_id: 0,
+            fbconfig: null_mut(),
+            hide: false,
+            has_alpha: false,
+            use_actual_position: false,
+            event: None,
+            velocity: XVelocity {
+                x_speed: 0.0,
+                last_x_location: 0,
+            },
+            minimized: false,
+            opacity: 1.0,
+            net_wm_opacity: 1.0,
+            rule: RuleSettings::default(),
+            window_type: WindowType::Normal,
+            workspace: None,
+            workspace_hidden: false,
+            floating: false,
+            state: WindowState::default(),
+            decorated: false,
+            strut: None,
+            transient_for: None,
+            bypass_compositor: 0,
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 pub struct XVelocity {
     pub x_speed: f64, // the x speed of the window
     pub last_x_location: i32, // the last x location of the window (for calculating the speed)
-}
\ No newline at end of file
+}
+
+// _NET_WM_WINDOW_TYPE, without the prefix
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WindowType {
+    Normal,
+    Desktop,
+    Dock,
+    Toolbar,
+    Menu,
+    Utility,
+    Splash,
+    Dialog,
+    DropdownMenu,
+    PopupMenu,
+    Tooltip,
+    Notification,
+    Combo,
+    Dnd,
+}
+
+impl WindowType {
+    // takes either the atom name (_NET_WM_WINDOW_TYPE_DOCK) or the name used in the config (dock)
+    pub fn parse(name: &str) -> Option<WindowType> {
+        match name.trim_start_matches("_NET_WM_WINDOW_TYPE_").to_lowercase().as_str() {
+            "normal" => Some(WindowType::Normal),
+            "desktop" => Some(WindowType::Desktop),
+            "dock" => Some(WindowType::Dock),
+            "toolbar" => Some(WindowType::Toolbar),
+            "menu" => Some(WindowType::Menu),
+            "utility" => Some(WindowType::Utility),
+            "splash" => Some(WindowType::Splash),
+            "dialog" => Some(WindowType::Dialog),
+            "dropdown_menu" => Some(WindowType::DropdownMenu),
+            "popup_menu" => Some(WindowType::PopupMenu),
+            "tooltip" => Some(WindowType::Tooltip),
+            "notification" => Some(WindowType::Notification),
+            "combo" => Some(WindowType::Combo),
+            "dnd" => Some(WindowType::Dnd),
+            _ => None,
+        }
+    }
+
+    // whether windows of this type get a frame
+    pub fn decorated(&self) -> bool {
+        matches!(self, WindowType::Normal | WindowType::Dialog | WindowType::Utility | WindowType::Toolbar)
+    }
+
+    // whether windows of this type stay wherever they asked to be instead of getting centred;
+    // panels, menus and popups all know better than we do where they should go
+    pub fn keeps_position(&self) -> bool {
+        matches!(self, WindowType::Dock | WindowType::Desktop | WindowType::Menu | WindowType::DropdownMenu | WindowType::PopupMenu
+            | WindowType::Tooltip | WindowType::Notification | WindowType::Combo | WindowType::Dnd)
+    }
+
+    // panels, the desktop and popups that go away on their own shouldn't steal the keyboard
+    pub fn takes_focus(&self) -> bool {
+        !matches!(self, WindowType::Dock | WindowType::Desktop | WindowType::Tooltip | WindowType::Notification | WindowType::Dnd)
+    }
+
+    // the same ones come and go too quickly to wobble, or (panels) shouldn't move at all
+    pub fn wobbles(&self) -> bool {
+        !self.keeps_position()
+    }
+}
+
+// _NET_WM_STATE, without the prefix. sticky isn't in here since that's the window's workspace being None,
+// and neither is hidden, which is just whether it's minimized
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct WindowState {
+    pub fullscreen: bool,
+    pub maximized_vert: bool,
+    pub maximized_horz: bool,
+    pub above: bool,
+    pub below: bool,
+    pub shaded: bool,
+    pub skip_taskbar: bool,
+    pub demands_attention: bool,
+}
+
+impl WindowState {
+    // the flag for a state, taking either the atom name (_NET_WM_STATE_ABOVE) or just the end of it (ABOVE)
+    pub fn flag(&mut self, name: &str) -> Option<&mut bool> {
+        match name.trim_start_matches("_NET_WM_STATE_") {
+            "FULLSCREEN" => Some(&mut self.fullscreen),
+            "MAXIMIZED_VERT" => Some(&mut self.maximized_vert),
+            "MAXIMIZED_HORZ" => Some(&mut self.maximized_horz),
+            "ABOVE" => Some(&mut self.above),
+            "BELOW" => Some(&mut self.below),
+            "SHADED" => Some(&mut self.shaded),
+            "SKIP_TASKBAR" => Some(&mut self.skip_taskbar),
+            "DEMANDS_ATTENTION" => Some(&mut self.demands_attention),
+            _ => None,
+        }
+    }
+
+    // atom names of every state that's set
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.fullscreen, "_NET_WM_STATE_FULLSCREEN"),
+            (self.maximized_vert, "_NET_WM_STATE_MAXIMIZED_VERT"),
+            (self.maximized_horz, "_NET_WM_STATE_MAXIMIZED_HORZ"),
+            (self.above, "_NET_WM_STATE_ABOVE"),
+            (self.below, "_NET_WM_STATE_BELOW"),
+            (self.shaded, "_NET_WM_STATE_SHADED"),
+            (self.skip_taskbar, "_NET_WM_STATE_SKIP_TASKBAR"),
+            (self.demands_attention, "_NET_WM_STATE_DEMANDS_ATTENTION"),
+        ].iter().filter(|(set, _)| *set).map(|(_, name)| *name).collect()
+    }
+
+    pub fn maximized(&self) -> bool {
+        self.maximized_vert || self.maximized_horz
+    }
+}
+
+// a window that's gone from x but still has its closing animation to play
+pub struct ClosingWindow {
+    pub window: CumWindow, // what the window looked like when it went away
+    pub mesh: Option<WobblyMesh>, // keeps wobbling while it closes
+    pub textures: WindowTextures, // the last picture we got of the window
+    pub blur_region: Option<Vec<(i32, i32, i32, i32)>>, // what it had _KDE_NET_WM_BLUR_BEHIND_REGION set to
+    pub animation: Animation,
+}
@@ -0,0 +1,273 @@
+// turns the wallpaper image into a screen sized picture, at the screen's full resolution
+use std::env;
+use std::fs;
+use std::num::NonZeroU32;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use stb_image::image::LoadResult;
+use crate::fr;
+use crate::layout::Rect;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WallpaperMode {
+    Fill, // scale to cover the whole screen, cropping whatever sticks out
+    Fit, // scale to fit inside the screen, the rest gets the letterbox colour
+    Stretch, // scale to exactly the screen size, ignoring the aspect ratio
+    Centre, // no scaling, in the middle of the screen
+    Tile, // no scaling, repeated from the top left
+}
+
+impl WallpaperMode {
+    pub fn parse(name: &str) -> Option<WallpaperMode> {
+        match name {
+            "fill" => Some(WallpaperMode::Fill),
+            "fit" => Some(WallpaperMode::Fit),
+            "stretch" => Some(WallpaperMode::Stretch),
+            "centre" | "center" => Some(WallpaperMode::Centre),
+            "tile" => Some(WallpaperMode::Tile),
+            _ => None,
+        }
+    }
+}
+
+// an rgba image, row by row
+#[derive(Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+// hdr images come in as linear floats with no upper limit, so squash them into 0-255 (reinhard, then gamma)
+fn tonemap(value: f32) -> u8 {
+    let mapped = value.max(0.0) / (1.0 + value.max(0.0));
+    (mapped.powf(1.0 / 2.2) * 255.0).round() as u8
+}
+
+// anything stb_image can read: png, jpeg, ppm/pgm/pnm, hdr and a few more, always as rgba
+pub fn load(path: &Path) -> Result<RgbaImage, String> {
+    match stb_image::image::load_with_depth(path, 4, false) {
+        LoadResult::ImageU8(image) => Ok(RgbaImage { width: image.width as u32, height: image.height as u32, data: image.data }),
+        LoadResult::ImageF32(image) => {
+            let data = image.data.chunks(4)
+                .flat_map(|pixel| [tonemap(pixel[0]), tonemap(pixel[1]), tonemap(pixel[2]), (pixel[3].clamp(0.0, 1.0) * 255.0) as u8])
+                .collect();
+            Ok(RgbaImage { width: image.width as u32, height: image.height as u32, data })
+        }
+        LoadResult::Error(e) => Err(e),
+    }
+}
+
+// where resized wallpapers are kept between runs: $XDG_CACHE_HOME/cwm, then ~/.cache/cwm
+fn cache_dir() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("cwm"));
+    }
+    env::var("HOME").ok().map(|home| PathBuf::from(home).join(".cache").join("cwm"))
+}
+
+// the cache file for this source image at this size; changing the file (or its modification time) makes a new one
+// 64-bit FNV-1a. the cache outlives us, so the name has to come out the same on every run and every rust version,
+// which DefaultHasher doesn't promise
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn cache_path(source: &Path, width: u32, height: u32, mode: WallpaperMode) -> Option<PathBuf> {
+    let metadata = fs::metadata(source).ok()?;
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos() as u64);
+    let mut hash = 0xcbf29ce484222325;
+    hash = fnv1a(hash, fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf()).as_os_str().as_bytes());
+    hash = fnv1a(hash, &metadata.len().to_le_bytes());
+    hash = fnv1a(hash, &modified.to_le_bytes());
+    hash = fnv1a(hash, &[mode as u8]);
+    Some(cache_dir()?.join(format!("wallpaper-{:016x}-{}x{}.rgba", hash, width, height)))
+}
+
+// lanczos resizes the image to exactly width x height
+fn resize(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let mut src = fr::Image::from_vec_u8(
+        NonZeroU32::new(image.width).unwrap(),
+        NonZeroU32::new(image.height).unwrap(),
+        image.data.clone(),
+        fr::PixelType::U8x4,
+    ).unwrap();
+    // resizing has to happen with the colours multiplied by alpha, or transparent pixels bleed into their neighbours
+    let alpha_mul_div = fr::MulDiv::default();
+    alpha_mul_div.multiply_alpha_inplace(&mut src.view_mut()).unwrap();
+
+    let mut dst = fr::Image::new(NonZeroU32::new(width).unwrap(), NonZeroU32::new(height).unwrap(), fr::PixelType::U8x4);
+    let mut dst_view = dst.view_mut();
+    let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Lanczos3));
+    resizer.resize(&src.view(), &mut dst_view).unwrap();
+    alpha_mul_div.divide_alpha_inplace(&mut dst_view).unwrap();
+
+    RgbaImage { width, height, data: dst.buffer().to_vec() }
+}
+
+// copies image onto a screen sized canvas with its top left corner at (x, y), cutting off anything outside
+fn blit(canvas: &mut RgbaImage, image: &RgbaImage, x: i64, y: i64) {
+    for row in 0..image.height as i64 {
+        let canvas_y = y + row;
+        if canvas_y < 0 || canvas_y >= canvas.height as i64 {
+            continue;
+        }
+        let left = x.max(0);
+        let right = (x + image.width as i64).min(canvas.width as i64);
+        if right <= left {
+            return;
+        }
+        let src_start = ((row * image.width as i64 + (left - x)) * 4) as usize;
+        let dst_start = ((canvas_y * canvas.width as i64 + left) * 4) as usize;
+        let length = ((right - left) * 4) as usize;
+        canvas.data[dst_start..dst_start + length].copy_from_slice(&image.data[src_start..src_start + length]);
+    }
+}
+
+// a width x height image that's all one colour
+pub fn solid(width: u32, height: u32, color: [f32; 3]) -> RgbaImage {
+    let pixel = [(color[0] * 255.0) as u8, (color[1] * 255.0) as u8, (color[2] * 255.0) as u8, 255];
+    RgbaImage { width, height, data: pixel.repeat((width * height) as usize) }
+}
+
+// the wallpaper as it should appear on a width x height screen
+pub fn render(image: &RgbaImage, source: &Path, width: u32, height: u32, mode: WallpaperMode, letterbox: [f32; 3]) -> RgbaImage {
+    let mut canvas = solid(width, height, letterbox);
+
+    match mode {
+        WallpaperMode::Centre => {
+            blit(&mut canvas, image, (width as i64 - image.width as i64) / 2, (height as i64 - image.height as i64) / 2);
+            return canvas;
+        }
+        WallpaperMode::Tile => {
+            for y in (0..height).step_by(image.height as usize) {
+                for x in (0..width).step_by(image.width as usize) {
+                    blit(&mut canvas, image, x as i64, y as i64);
+                }
+            }
+            return canvas;
+        }
+        _ => {}
+    }
+
+    // the rest need resizing, which is slow for big images, so try the cache first
+    let (scaled_width, scaled_height) = match mode {
+        WallpaperMode::Stretch => (width, height),
+        _ => {
+            let scale_x = width as f64 / image.width as f64;
+            let scale_y = height as f64 / image.height as f64;
+            let scale = if mode == WallpaperMode::Fill { scale_x.max(scale_y) } else { scale_x.min(scale_y) };
+            (((image.width as f64 * scale).round() as u32).max(1), ((image.height as f64 * scale).round() as u32).max(1))
+        }
+    };
+    let cache = cache_path(source, scaled_width, scaled_height, mode);
+    let cached = cache.as_ref()
+        .and_then(|path| fs::read(path).ok())
+        .filter(|data| data.len() == (scaled_width * scaled_height * 4) as usize);
+    let scaled = match cached {
+        Some(data) => RgbaImage { width: scaled_width, height: scaled_height, data },
+        None => {
+            let scaled = resize(image, scaled_width, scaled_height);
+            if let Some(path) = &cache {
+                let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(path, &scaled.data));
+                if let Err(e) = written {
+                    println!("could not cache the wallpaper at {}: {}", path.display(), e);
+                }
+            }
+            scaled
+        }
+    };
+    blit(&mut canvas, &scaled, (width as i64 - scaled_width as i64) / 2, (height as i64 - scaled_height as i64) / 2);
+    canvas
+}
+
+// the wallpaper on a width x height screen made up of monitors, each one getting it as if it was the whole screen
+pub fn render_monitors(image: &RgbaImage, source: &Path, width: u32, height: u32, monitors: &[Rect], mode: WallpaperMode, letterbox: [f32; 3]) -> RgbaImage {
+    let mut canvas = solid(width, height, letterbox);
+    for monitor in monitors {
+        let picture = render(image, source, monitor.width.max(1) as u32, monitor.height.max(1) as u32, mode, letterbox);
+        blit(&mut canvas, &picture, monitor.x as i64, monitor.y as i64);
+    }
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLACK: [f32; 3] = [0.0, 0.0, 0.0];
+
+    fn filled(width: u32, height: u32, colour: [u8; 4]) -> RgbaImage {
+        RgbaImage { width, height, data: colour.repeat((width * height) as usize) }
+    }
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        let start = ((y * image.width + x) * 4) as usize;
+        image.data[start..start + 4].try_into().unwrap()
+    }
+
+    // which pixels are (mostly) red, one string per row: resizing can blur the edges a little, but not this much
+    fn red_rows(image: &RgbaImage) -> Vec<String> {
+        (0..image.height).map(|y| (0..image.width).map(|x| if pixel(image, x, y)[0] > 127 { '#' } else { '.' }).collect()).collect()
+    }
+
+    #[test]
+    fn fnv1a_matches_the_reference_values() {
+        assert_eq!(fnv1a(0xcbf29ce484222325, b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(0xcbf29ce484222325, b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(fnv1a(0xcbf29ce484222325, b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn blit_cuts_off_whatever_is_outside_the_canvas() {
+        let mut canvas = filled(4, 3, [0; 4]);
+        blit(&mut canvas, &filled(3, 2, RED), -1, 2);
+        assert_eq!(red_rows(&canvas), vec!["....", "....", "##.."]);
+
+        let mut canvas = filled(4, 3, [0; 4]);
+        blit(&mut canvas, &filled(3, 2, RED), 3, -1);
+        assert_eq!(red_rows(&canvas), vec!["...#", "....", "...."]);
+
+        let mut canvas = filled(4, 3, [0; 4]);
+        blit(&mut canvas, &filled(3, 2, RED), 4, 0);
+        assert_eq!(red_rows(&canvas), vec!["....", "....", "...."]);
+    }
+
+    #[test]
+    fn centre_letterboxes_small_images_and_crops_big_ones() {
+        let path = Path::new("/nonexistent/wallpaper.png");
+        let small = render(&filled(2, 1, RED), path, 4, 3, WallpaperMode::Centre, [0.0, 0.0, 1.0]);
+        assert_eq!(red_rows(&small), vec!["....", ".##.", "...."]);
+        assert_eq!(pixel(&small, 0, 0), [0, 0, 255, 255]);
+
+        let big = render(&filled(6, 5, RED), path, 4, 3, WallpaperMode::Centre, BLACK);
+        assert_eq!(red_rows(&big), vec!["####", "####", "####"]);
+    }
+
+    #[test]
+    fn tile_repeats_from_the_top_left() {
+        let mut image = filled(2, 2, [0, 0, 0, 255]);
+        image.data[..4].copy_from_slice(&RED);
+        let tiled = render(&image, Path::new("/nonexistent/wallpaper.png"), 5, 3, WallpaperMode::Tile, BLACK);
+        assert_eq!(red_rows(&tiled), vec!["#.#.#", ".....", "#.#.#"]);
+    }
+
+    #[test]
+    fn fit_letterboxes_and_fill_crops_to_keep_the_aspect_ratio() {
+        // a source that doesn't exist has no cache file, so these always resize
+        let path = Path::new("/nonexistent/wallpaper.png");
+        let fit = render(&filled(4, 2, RED), path, 8, 8, WallpaperMode::Fit, BLACK);
+        assert_eq!(red_rows(&fit), vec!["........", "........", "########", "########",
+                                         "########", "########", "........", "........"]);
+        assert_eq!(pixel(&fit, 0, 0), [0, 0, 0, 255]);
+
+        let fill = render(&filled(4, 2, RED), path, 8, 8, WallpaperMode::Fill, BLACK);
+        assert!(red_rows(&fill).iter().all(|row| row == "########"));
+
+        let stretch = render(&filled(4, 2, RED), path, 8, 8, WallpaperMode::Stretch, BLACK);
+        assert!(red_rows(&stretch).iter().all(|row| row == "########"));
+    }
+}